@@ -0,0 +1,491 @@
+// Functions that are implemented in Rust rather than in recalc.
+// They can be overridden by user definitions, like any other function.
+// Most builtins are strict: if any of their arguments is undefined, so is the result,
+// so they never get undefined arguments.
+
+use crate::expression::Expression;
+use crate::math::number_theory as nt;
+use crate::math::{self, List, Matrix, Polynomial, Quantity, Residue, Undefined, Value};
+use crate::native::NativeFunction;
+use crate::native::Unevaluated;
+use crate::native::{self, Arguments, FunctionRef};
+use crate::program::Program;
+use malachite::num::arithmetic::traits::{Abs, Pow};
+use malachite::num::basic::traits::Zero;
+use malachite::rounding_modes::RoundingMode;
+use malachite::{Integer, Rational};
+
+fn number(value: &Value) -> Result<&Rational, Undefined> {
+    match value {
+        Value::Number(n) => Ok(n),
+        _ => Err(Undefined::TypeMismatch),
+    }
+}
+
+fn integer(value: &Value) -> Result<Integer, Undefined> {
+    nt::integer(number(value)?)
+}
+
+fn matrix(value: &Value) -> Result<&Matrix, Undefined> {
+    match value {
+        Value::Matrix(m) => Ok(m),
+        _ => Err(Undefined::TypeMismatch),
+    }
+}
+
+fn len(args: &[Value]) -> Value {
+    match &args[0] {
+        Value::List(list) => Value::Number(Rational::from(list.len())),
+        _ => Value::Undefined(Undefined::TypeMismatch),
+    }
+}
+
+fn transpose(args: &[Value]) -> Value {
+    matrix(&args[0]).map(Matrix::transpose).into()
+}
+
+fn det(args: &[Value]) -> Value {
+    matrix(&args[0]).and_then(Matrix::determinant).into()
+}
+
+fn inverse(args: &[Value]) -> Value {
+    matrix(&args[0]).and_then(Matrix::inverse).into()
+}
+
+fn linsolve(args: &[Value]) -> Value {
+    (|| matrix(&args[0])?.solve(matrix(&args[1])?))().into()
+}
+
+fn floor(args: &[Value]) -> Value {
+    number(&args[0])
+        .map(|n| nt::round(n, RoundingMode::Floor))
+        .into()
+}
+
+fn ceil(args: &[Value]) -> Value {
+    number(&args[0])
+        .map(|n| nt::round(n, RoundingMode::Ceiling))
+        .into()
+}
+
+fn round(args: &[Value]) -> Value {
+    number(&args[0])
+        .map(|n| nt::round(n, RoundingMode::Nearest))
+        .into()
+}
+
+fn trunc(args: &[Value]) -> Value {
+    number(&args[0])
+        .map(|n| nt::round(n, RoundingMode::Down))
+        .into()
+}
+
+fn modulo(args: &[Value]) -> Value {
+    (|| nt::modulo(number(&args[0])?, number(&args[1])?))().into()
+}
+
+fn gcd(args: &[Value]) -> Value {
+    (|| Ok(nt::gcd(&integer(&args[0])?, &integer(&args[1])?)))().into()
+}
+
+fn lcm(args: &[Value]) -> Value {
+    (|| Ok(nt::lcm(&integer(&args[0])?, &integer(&args[1])?)))().into()
+}
+
+fn numerator(args: &[Value]) -> Value {
+    number(&args[0])
+        .map(|n| {
+            let numerator = Rational::from(n.numerator_ref());
+            if *n < 0 {
+                -numerator
+            } else {
+                numerator
+            }
+        })
+        .into()
+}
+
+fn denominator(args: &[Value]) -> Value {
+    number(&args[0])
+        .map(|n| Rational::from(n.denominator_ref().clone()))
+        .into()
+}
+
+fn is_prime(args: &[Value]) -> Value {
+    integer(&args[0])
+        .map(|n| Rational::from(u32::from(nt::is_prime(&n))))
+        .into()
+}
+
+fn factor(args: &[Value]) -> Value {
+    match integer(&args[0]).and_then(|n| nt::factor(&n)) {
+        Ok(factors) => Value::List(List::from_values(factors.into_iter().map(Value::from))),
+        Err(u) => Value::Undefined(u),
+    }
+}
+
+fn modpow(args: &[Value]) -> Value {
+    (|| {
+        nt::modpow(
+            &integer(&args[0])?,
+            &integer(&args[1])?,
+            &integer(&args[2])?,
+        )
+    })()
+    .into()
+}
+
+fn binomial(args: &[Value]) -> Value {
+    (|| Ok(nt::binomial(&integer(&args[0])?, &integer(&args[1])?)))().into()
+}
+
+fn isqrt(args: &[Value]) -> Value {
+    number(&args[0]).and_then(nt::isqrt).into()
+}
+
+fn residue(args: &[Value]) -> Value {
+    (|| Residue::new(number(&args[0])?, number(&args[1])?))().into()
+}
+
+fn lift(args: &[Value]) -> Value {
+    match &args[0] {
+        Value::Mod(r) => Value::from(r.value().clone()),
+        _ => Value::Undefined(Undefined::TypeMismatch),
+    }
+}
+
+fn convergents(args: &[Value]) -> Value {
+    match number(&args[0]) {
+        Ok(n) => list_of_numbers(nt::convergents(n).into_iter()),
+        Err(u) => Value::Undefined(u),
+    }
+}
+
+// bestApprox(x, n) returns the closest fraction to x with a denominator of at most n.
+fn best_approx(args: &[Value]) -> Value {
+    (|| {
+        let max_denominator = integer(&args[1])?;
+        if max_denominator < 1 {
+            return Err(Undefined::OutOfDomain);
+        }
+        Ok(nt::best_approximation(
+            number(&args[0])?,
+            max_denominator.unsigned_abs_ref(),
+        ))
+    })()
+    .into()
+}
+
+// Numbers are constant polynomials.
+fn polynomial(value: &Value) -> Result<Polynomial, Undefined> {
+    match value {
+        Value::Poly(p) => Ok(p.clone()),
+        Value::Number(n) => Ok(Polynomial::constant(n.clone())),
+        _ => Err(Undefined::TypeMismatch),
+    }
+}
+
+fn list_of_numbers(numbers: impl DoubleEndedIterator<Item = Rational>) -> Value {
+    Value::List(List::from_values(numbers.map(Value::Number)))
+}
+
+// poly([a, b, c]) = a*X^2 + b*X + c
+fn poly(args: &Arguments) -> Result<Value, Unevaluated> {
+    let list = match args.get(0)? {
+        Value::List(list) => list,
+        u @ Value::Undefined(_) => return Ok(u.clone()),
+        _ => return Ok(Value::Undefined(Undefined::TypeMismatch)),
+    };
+    let mut coefficients = vec![];
+    for element in list.iter() {
+        match native::element_value(element)? {
+            Value::Number(n) => coefficients.push(n.clone()),
+            u @ Value::Undefined(_) => return Ok(u.clone()),
+            _ => return Ok(Value::Undefined(Undefined::TypeMismatch)),
+        }
+    }
+    coefficients.reverse();
+    Ok(Polynomial::new(coefficients).into())
+}
+
+fn coeffs(args: &[Value]) -> Value {
+    match polynomial(&args[0]) {
+        Ok(p) if p.is_zero() => list_of_numbers([Rational::ZERO].into_iter()),
+        Ok(p) => list_of_numbers(p.coefficients().iter().rev().cloned()),
+        Err(u) => Value::Undefined(u),
+    }
+}
+
+fn poly_div(args: &[Value]) -> Value {
+    match (|| polynomial(&args[0])?.div_rem(&polynomial(&args[1])?))() {
+        Ok((quotient, remainder)) => {
+            Value::List(List::from_values([quotient.into(), remainder.into()]))
+        }
+        Err(u) => Value::Undefined(u),
+    }
+}
+
+fn poly_gcd(args: &[Value]) -> Value {
+    (|| Ok(polynomial(&args[0])?.gcd(&polynomial(&args[1])?)))().into()
+}
+
+fn deriv(args: &[Value]) -> Value {
+    polynomial(&args[0]).map(|p| p.derivative()).into()
+}
+
+fn eval(args: &[Value]) -> Value {
+    (|| Ok(polynomial(&args[0])?.eval(number(&args[1])?)))().into()
+}
+
+fn roots(args: &[Value]) -> Value {
+    match polynomial(&args[0]).and_then(|p| p.rational_roots()) {
+        Ok(roots) => list_of_numbers(roots.into_iter()),
+        Err(u) => Value::Undefined(u),
+    }
+}
+
+// if(c, x, y) returns x if c is nonzero, or y if c is zero.
+// Only the chosen argument is evaluated, so the other one may be undefined.
+fn if_(args: &Arguments) -> Result<Value, Unevaluated> {
+    Ok(match args.get(0)? {
+        Value::Number(c) if *c != 0 => args.get(1)?.clone(),
+        Value::Number(_) => args.get(2)?.clone(),
+        u @ Value::Undefined(_) => u.clone(),
+        _ => Value::Undefined(Undefined::TypeMismatch),
+    })
+}
+
+// Numbers are quantities without units.
+fn quantity(value: &Value) -> Result<Quantity, Undefined> {
+    match value {
+        Value::Quantity(q) => Ok(q.clone()),
+        Value::Number(n) => Ok(Quantity::number(n.clone())),
+        _ => Err(Undefined::TypeMismatch),
+    }
+}
+
+// x in units, which is compiled from the in operator.
+pub fn convert(args: &[Value]) -> Value {
+    (|| quantity(&args[0])?.convert(&quantity(&args[1])?))().into()
+}
+
+// The value of a number argument of a lazy function. If it's undefined, so is the result.
+fn number_arg<'a>(
+    args: &Arguments<'a>,
+    i: usize,
+) -> Result<Result<&'a Rational, Undefined>, Unevaluated> {
+    Ok(match args.get(i)? {
+        Value::Number(n) => Ok(n),
+        Value::Undefined(u) => Err(u.clone()),
+        _ => Err(Undefined::TypeMismatch),
+    })
+}
+
+fn unary_function<'a>(args: &Arguments<'a>, i: usize) -> Result<&'a FunctionRef, Undefined> {
+    match args.function(i) {
+        Some(f) if f.n_params() == 1 => Ok(f),
+        _ => Err(Undefined::TypeMismatch),
+    }
+}
+
+fn value(n: Rational) -> Expression {
+    Expression::Value(Value::Number(n))
+}
+
+// f(x)
+fn call(f: &FunctionRef, x: Rational) -> Expression {
+    f.call(vec![value(x)])
+}
+
+fn rewrite(result: Result<Expression, Undefined>) -> Expression {
+    result.unwrap_or_else(|u| Expression::Value(Value::Undefined(u)))
+}
+
+// Simpson's rule is only trusted after dividing the interval into at least 2^SIMPSON_MIN_DEPTH parts,
+// so narrow features of f aren't missed.
+const SIMPSON_MIN_DEPTH: u64 = 3;
+// A part that still isn't smooth after this many halvings, like one with a jump in it,
+// is so narrow that its error is negligible, unless f is unbounded there.
+const SIMPSON_MAX_DEPTH: u64 = 200;
+
+// One step of adaptive Simpson integration:
+// simpson(f, a, b, tolerance, f(a), f(m), f(b), f((a + m)/2), f((m + b)/2), depth),
+// where m = (a + b)/2, and the interval is one of 2^depth parts of the whole interval.
+// If Simpson's rule gives about the same result for the whole interval and for its two halves,
+// the result is close enough. Otherwise, each half is integrated separately, with half the tolerance,
+// so the errors of all the parts add up to at most the tolerance of the whole interval.
+fn simpson(args: &Arguments) -> Result<Expression, Unevaluated> {
+    let mut values = vec![];
+    for i in 4..9 {
+        values.push(number_arg(args, i)?);
+    }
+    Ok(rewrite((|| {
+        let f = unary_function(args, 0)?;
+        let [a, b, tolerance, depth] = [1, 2, 3, 9].map(|i| number(args.get(i).unwrap()).unwrap());
+        let [fa, fm, fb, fl, fr] =
+            <[_; 5]>::try_from(values.into_iter().collect::<Result<Vec<_>, _>>()?).unwrap();
+        let m = (a + b) / Rational::from(2);
+        let six = Rational::from(6);
+        let four = Rational::from(4);
+        let whole = (b - a) / &six * (fa + &four * fm + fb);
+        let left = (&m - a) / &six * (fa + &four * fl + fm);
+        let right = (b - &m) / &six * (fm + &four * fr + fb);
+        let sum = left + right;
+        let difference = &sum - whole;
+        let depth = u64::try_from(depth).unwrap();
+        // For smooth functions, the error of the corrected estimate is about difference/15,
+        // and half of the tolerance is left for rounding it.
+        let close_enough =
+            (&difference).abs() * Rational::from(2) <= tolerance * Rational::from(15);
+        let negligible = depth >= SIMPSON_MAX_DEPTH
+            && (&difference).abs() <= tolerance * Rational::from(2).pow(depth);
+        if (depth >= SIMPSON_MIN_DEPTH && close_enough) || negligible {
+            // Rounding to a simple fraction keeps the denominators of the sum small.
+            let estimate = sum + difference / Rational::from(15);
+            let rounding = tolerance / Rational::from(2);
+            return Ok(value(nt::simplest_between(
+                &(&estimate - &rounding),
+                &(&estimate + &rounding),
+            )));
+        }
+        if depth >= SIMPSON_MAX_DEPTH {
+            return Err(Undefined::NoConvergence);
+        }
+        let half_tolerance = tolerance / Rational::from(2);
+        let half = |a: &Rational, b: &Rational, fa: &Rational, fm: &Rational, fb: &Rational| {
+            let quarter = (b - a) / &four;
+            Expression::CallNative(
+                NativeFunction::rewrite(simpson),
+                vec![
+                    Expression::Function(f.clone()),
+                    value(a.clone()),
+                    value(b.clone()),
+                    value(half_tolerance.clone()),
+                    value(fa.clone()),
+                    value(fm.clone()),
+                    value(fb.clone()),
+                    call(f, a + &quarter),
+                    call(f, b - &quarter),
+                    value(Rational::from(depth + 1)),
+                ],
+            )
+        };
+        Ok(Expression::Add(
+            Box::new(half(a, &m, fa, fl, fm)),
+            Box::new(half(&m, b, fm, fr, fb)),
+        ))
+    })()))
+}
+
+// approx(x, tolerance) is the simplest fraction within the tolerance of x.
+fn approx(args: &[Value]) -> Value {
+    let (x, tolerance) = (&args[0], &args[1]);
+    (|| {
+        let (x, tolerance) = (number(x)?, number(tolerance)?);
+        Ok(nt::simplest_between(&(x - tolerance), &(x + tolerance)))
+    })()
+    .into()
+}
+
+// integrate(f, a, b, tolerance) approximates the integral of f from a to b.
+fn integrate(args: &Arguments) -> Result<Expression, Unevaluated> {
+    let (a, b, tolerance) = (
+        number_arg(args, 1)?,
+        number_arg(args, 2)?,
+        number_arg(args, 3)?,
+    );
+    Ok(rewrite((|| {
+        let f = unary_function(args, 0)?;
+        let (a, b, tolerance) = (a?, b?, tolerance?);
+        if *tolerance <= 0 {
+            return Err(Undefined::OutOfDomain);
+        }
+        let point = |k: u64| a + (b - a) * Rational::from_unsigneds(k, 4);
+        let integral = Expression::CallNative(
+            NativeFunction::rewrite(simpson),
+            vec![
+                Expression::Function(f.clone()),
+                value(a.clone()),
+                value(b.clone()),
+                value(tolerance / Rational::from(2)),
+                call(f, a.clone()),
+                call(f, point(2)),
+                call(f, b.clone()),
+                call(f, point(1)),
+                call(f, point(3)),
+                value(Rational::ZERO),
+            ],
+        );
+        Ok(Expression::CallNative(
+            NativeFunction::strict(approx),
+            vec![integral, value(tolerance / Rational::from(2))],
+        ))
+    })()))
+}
+
+// derivative(f, x, h) approximates the derivative of f at x, using the values of f at x ± h and x ± 2h.
+// It's exact if f is a polynomial of degree 4 or less.
+fn derivative(args: &Arguments) -> Result<Expression, Unevaluated> {
+    let (x, h) = (number_arg(args, 1)?, number_arg(args, 2)?);
+    Ok(rewrite((|| {
+        let f = unary_function(args, 0)?;
+        let (x, h) = (x?, h?);
+        if *h == 0 {
+            return Err(Undefined::OutOfDomain);
+        }
+        let at = |k: i64| Box::new(call(f, x + h * Rational::from(k)));
+        let eight = |e| Box::new(Expression::Mul(Box::new(value(Rational::from(8))), e));
+        // (f(x - 2h) - 8f(x - h) + 8f(x + h) - f(x + 2h)) / 12h
+        let numerator = Expression::Sub(
+            Box::new(Expression::Add(
+                Box::new(Expression::Sub(at(-2), eight(at(-1)))),
+                eight(at(1)),
+            )),
+            at(2),
+        );
+        Ok(Expression::Div(
+            Box::new(numerator),
+            Box::new(value(Rational::from(12) * h)),
+        ))
+    })()))
+}
+
+pub fn define_builtins(program: &mut Program) {
+    program.define_native("if", 3, NativeFunction::lazy(if_));
+    program.define_native("len", 1, NativeFunction::strict(len));
+    program.define_native("transpose", 1, NativeFunction::strict(transpose));
+    program.define_native("det", 1, NativeFunction::strict(det));
+    program.define_native("inverse", 1, NativeFunction::strict(inverse));
+    program.define_native("linsolve", 2, NativeFunction::strict(linsolve));
+    program.define_native("floor", 1, NativeFunction::strict(floor));
+    program.define_native("ceil", 1, NativeFunction::strict(ceil));
+    program.define_native("round", 1, NativeFunction::strict(round));
+    program.define_native("trunc", 1, NativeFunction::strict(trunc));
+    program.define_native("mod", 2, NativeFunction::strict(modulo));
+    program.define_native("gcd", 2, NativeFunction::strict(gcd));
+    program.define_native("lcm", 2, NativeFunction::strict(lcm));
+    program.define_native("numerator", 1, NativeFunction::strict(numerator));
+    program.define_native("denominator", 1, NativeFunction::strict(denominator));
+    program.define_native("isPrime", 1, NativeFunction::strict(is_prime));
+    program.define_native("factor", 1, NativeFunction::strict(factor));
+    program.define_native("modpow", 3, NativeFunction::strict(modpow));
+    program.define_native("binomial", 2, NativeFunction::strict(binomial));
+    program.define_native("isqrt", 1, NativeFunction::strict(isqrt));
+    program.define_native("convergents", 1, NativeFunction::strict(convergents));
+    program.define_native("bestApprox", 2, NativeFunction::strict(best_approx));
+    program.define_native("residue", 2, NativeFunction::strict(residue));
+    program.define_native("lift", 1, NativeFunction::strict(lift));
+    program.define_builtin_constant("X", Polynomial::x().into());
+    program.define_native("poly", 1, NativeFunction::lazy(poly));
+    program.define_native("coeffs", 1, NativeFunction::strict(coeffs));
+    program.define_native("polyDiv", 2, NativeFunction::strict(poly_div));
+    program.define_native("polyGcd", 2, NativeFunction::strict(poly_gcd));
+    program.define_native("deriv", 1, NativeFunction::strict(deriv));
+    program.define_native("eval", 2, NativeFunction::strict(eval));
+    program.define_native("roots", 1, NativeFunction::strict(roots));
+    for unit in math::units() {
+        program.define_builtin_constant(unit.name, Quantity::unit(unit).into());
+    }
+    program.define_native("integrate", 4, NativeFunction::rewrite(integrate));
+    program.define_native("derivative", 3, NativeFunction::rewrite(derivative));
+}
@@ -1,11 +1,16 @@
 use std::collections::{HashMap, HashSet};
+use std::ptr;
+use std::rc::Rc;
 
 use once_cell::sync::Lazy;
 
+use crate::builtin;
 use crate::expression::Expression;
+use crate::math::format::Format;
 use crate::math::Value;
+use crate::native::{FunctionRef, NativeFunction};
 use crate::parse as p;
-use crate::program::{Function, Program, WeakConstant, WeakFunction};
+use crate::program::{DefinitionInfo, Function, Program, WeakConstant, WeakFunction};
 
 #[derive(Debug, thiserror::Error)]
 pub enum CompilationError {
@@ -29,6 +34,12 @@ pub enum CompilationError {
     DuplicateParameter(String),
     #[error("function {0:?} got {1} argument(s) instead of {2}")]
     WrongNArgs(String, usize, usize),
+    #[error("all the rows of a matrix must have the same length")]
+    RaggedMatrix,
+    #[error("the base must be an integer from 2 to 36")]
+    BadBase,
+    #[error("while recompiling {0}, which depend on a replaced definition: {1}")]
+    BadDependents(String, Box<CompilationError>),
 }
 
 struct LocalContext {
@@ -51,7 +62,9 @@ fn compile_expression(
                 Expression::ArgumentIndex(index)
             } else if let Some(constant) = program.get_constant(name) {
                 Expression::Constant(WeakConstant::from(constant))
-            } else if program.get_function(name).is_some() {
+            } else if let Some(value) = program.get_builtin_constant(name) {
+                Expression::Value(value.clone())
+            } else if program.get_n_params(name).is_some() {
                 return Err(CompilationError::FunctionNotConstant(name.clone()));
             } else {
                 return Err(CompilationError::ConstantNotFound(name.clone()));
@@ -81,28 +94,125 @@ fn compile_expression(
             Box::new(compile_expression(left, program, context)?),
             Box::new(compile_expression(right, program, context)?),
         ),
-        p::Expression::Call(name, args) => {
-            let Some(function) = program.get_function(name) else {
-                return Err(if program.get_constant(name).is_some() {
-                    CompilationError::ConstantNotFunction(name.clone())
-                } else {
-                    CompilationError::FunctionNotFound(name.clone())
-                });
-            };
-            let args = args
+        p::Expression::Call(name, args) => compile_call(name, args, program, context)?,
+        p::Expression::List(elements) => Expression::List(
+            elements
                 .iter()
-                .map(|arg| compile_expression(arg, program, context))
-                .collect::<Result<Vec<Expression>, CompilationError>>()?;
-            let n_params = program.get_n_params(name).unwrap();
-            if args.len() != n_params {
-                return Err(CompilationError::WrongNArgs(
-                    name.clone(),
-                    args.len(),
-                    n_params,
-                ));
+                .map(|element| compile_expression(element, program, context))
+                .collect::<Result<Vec<Expression>, CompilationError>>()?,
+        ),
+        p::Expression::Matrix(rows) => {
+            let cols = rows[0].len();
+            if rows.iter().any(|row| row.len() != cols) {
+                return Err(CompilationError::RaggedMatrix);
+            }
+            Expression::Matrix(
+                cols,
+                rows.iter()
+                    .flatten()
+                    .map(|entry| compile_expression(entry, program, context))
+                    .collect::<Result<Vec<Expression>, CompilationError>>()?,
+            )
+        }
+        p::Expression::Index(list, index) => Expression::Index(
+            Box::new(compile_expression(list, program, context)?),
+            Box::new(compile_expression(index, program, context)?),
+        ),
+        p::Expression::Convert(value, units) => Expression::CallNative(
+            NativeFunction::strict(builtin::convert),
+            vec![
+                compile_expression(value, program, context)?,
+                compile_expression(units, program, context)?,
+            ],
+        ),
+    })
+}
+
+// Compiles an expression that isn't part of the program, e.g. an argument of a command.
+pub fn compile_standalone(
+    expr: &p::Expression,
+    program: &Program,
+) -> Result<Expression, CompilationError> {
+    compile_expression(expr, program, &GLOBAL_CONTEXT)
+}
+
+// A function can be passed by name to a native function, e.g. integrate(f, 0, 1, 1/1000).
+fn compile_function_ref(
+    name: &str,
+    program: &Program,
+    context: &LocalContext,
+) -> Option<FunctionRef> {
+    if context.param_indices.contains_key(name) {
+        return None;
+    }
+    let n_params = program.get_n_params(name)?;
+    Some(match program.get_native(name) {
+        Some(function) => FunctionRef::Native(n_params, function),
+        None => FunctionRef::User(
+            n_params,
+            WeakFunction::from(program.get_function(name).unwrap()),
+        ),
+    })
+}
+
+fn compile_call(
+    name: &String,
+    args: &[p::Expression],
+    program: &Program,
+    context: &LocalContext,
+) -> Result<Expression, CompilationError> {
+    let is_native = !context.param_indices.contains_key(name) && program.get_native(name).is_some();
+    let mut args = args
+        .iter()
+        .map(|arg| match arg {
+            p::Expression::Identifier(function) if is_native => {
+                match compile_function_ref(function, program, context) {
+                    Some(function) => Ok(Expression::Function(function)),
+                    None => compile_expression(arg, program, context),
+                }
             }
-            Expression::Call(WeakFunction::from(function), args)
+            _ => compile_expression(arg, program, context),
+        })
+        .collect::<Result<Vec<Expression>, CompilationError>>()?;
+    // A list can be indexed like a function: xs(0) or xs[0].
+    let list = if let Some(&index) = context.param_indices.get(name) {
+        Some(Expression::ArgumentIndex(index))
+    } else {
+        program
+            .get_constant(name)
+            .map(|constant| Expression::Constant(WeakConstant::from(constant)))
+            .or_else(|| {
+                program
+                    .get_builtin_constant(name)
+                    .map(|value| Expression::Value(value.clone()))
+            })
+    };
+    if let Some(list) = list {
+        if args.len() != 1 {
+            return Err(CompilationError::ConstantNotFunction(name.clone()));
         }
+        return Ok(Expression::Index(
+            Box::new(list),
+            Box::new(args.pop().unwrap()),
+        ));
+    }
+    let Some(n_params) = program.get_n_params(name) else {
+        return Err(CompilationError::FunctionNotFound(name.clone()));
+    };
+    if args.len() != n_params {
+        return Err(CompilationError::WrongNArgs(
+            name.clone(),
+            args.len(),
+            n_params,
+        ));
+    }
+    Ok(if let Some(function) = program.get_native(name) {
+        Expression::CallNative(function, args)
+    } else {
+        Expression::Call(
+            WeakFunction::from(program.get_function(name).unwrap()),
+            args,
+        )
     })
 }
 
@@ -137,7 +247,7 @@ fn compile_function(
     program
         .get_weak_function(function)
         .unwrap()
-        .init(Function::new(params.len(), result));
+        .init(Function::new(params.to_vec(), code.clone(), result));
     Ok(())
 }
 
@@ -193,14 +303,15 @@ fn assert_no_duplicate_assignments(code: &p::Code) -> Result<(), CompilationErro
 
 fn insert_uninit_global(
     global: &p::Expression,
+    info: Rc<DefinitionInfo>,
     program: &mut Program,
 ) -> Result<(), CompilationError> {
     match global {
         p::Expression::Identifier(name) => {
-            program.define_constant(name.clone());
+            program.define_constant(name.clone(), info);
         }
         p::Expression::Call(name, params) => {
-            program.define_function(name.clone(), params.len());
+            program.define_function(name.clone(), params.len(), info);
         }
         _ => return Err(CompilationError::BadEquation),
     }
@@ -208,29 +319,168 @@ fn insert_uninit_global(
 }
 
 fn insert_uninit_globals(code: &p::Code, program: &mut Program) -> Result<(), CompilationError> {
-    for stmt in &code.statements {
+    for (i, stmt) in code.statements.iter().enumerate() {
         let p::Statement::Assign(exprs) = stmt else {
             continue;
         };
-        for global in exprs.split_last().unwrap().1 {
-            insert_uninit_global(global, program)?;
+        let (value, globals) = exprs.split_last().unwrap();
+        let names = globals
+            .iter()
+            .filter_map(|global| match global {
+                p::Expression::Identifier(name) | p::Expression::Call(name, _) => {
+                    Some(name.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        let info = Rc::new(DefinitionInfo {
+            names,
+            value: value.clone(),
+            source: code.sources.get(i).cloned(),
+        });
+        for global in globals {
+            insert_uninit_global(global, info.clone(), program)?;
         }
     }
     Ok(())
 }
 
+// The base of a conversion like x in hex or x in base(3), which outputs x in that base.
+// It's only a conversion to a base if the name isn't defined otherwise.
+fn output_base(target: &p::Expression, program: &Program) -> Result<Option<u8>, CompilationError> {
+    let is_defined =
+        |name| program.get_constant(name).is_some() || program.get_n_params(name).is_some();
+    Ok(match target {
+        p::Expression::Identifier(name) if !is_defined(name) => match &name[..] {
+            "bin" => Some(2),
+            "oct" => Some(8),
+            "hex" => Some(16),
+            _ => None,
+        },
+        p::Expression::Call(name, args) if name == "base" && !is_defined(name) => match &args[..] {
+            [p::Expression::Number(n)] => match u8::try_from(n) {
+                Ok(base @ 2..=36) => Some(base),
+                _ => return Err(CompilationError::BadBase),
+            },
+            _ => return Err(CompilationError::BadBase),
+        },
+        _ => None,
+    })
+}
+
+// The names of the constants and functions that the code defines.
+fn assigned_names(code: &p::Code) -> Vec<String> {
+    let mut names = vec![];
+    for stmt in &code.statements {
+        let p::Statement::Assign(exprs) = stmt else {
+            continue;
+        };
+        for expr in exprs.split_last().unwrap().1 {
+            if let p::Expression::Identifier(name) | p::Expression::Call(name, _) = expr {
+                names.push(name.clone());
+            }
+        }
+    }
+    names
+}
+
+// Compiles the definitions that depend on the replaced ones again, so they refer to the new
+// definitions, along with the definitions that depend on those, and so on.
+fn recompile_dependents(
+    replaced: Vec<String>,
+    program: &mut Program,
+) -> Result<(), CompilationError> {
+    let mut changed = replaced.into_iter().collect::<HashSet<_>>();
+    let mut dependents = vec![];
+    loop {
+        let mut new_dependents = program
+            .names()
+            .filter(|&name| !changed.contains(name) && program.get_info(name).is_some())
+            .filter(|&name| {
+                program
+                    .dependencies(name)
+                    .iter()
+                    .any(|d| changed.contains(d))
+            })
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        if new_dependents.is_empty() {
+            break;
+        }
+        new_dependents.sort();
+        changed.extend(new_dependents.iter().cloned());
+        dependents.extend(new_dependents);
+    }
+    if dependents.is_empty() {
+        return Ok(());
+    }
+    // A statement like a = b = f(1) is compiled again as one statement.
+    let mut groups: Vec<(&DefinitionInfo, Vec<String>)> = vec![];
+    for name in &dependents {
+        let info = program.get_info(name).unwrap();
+        match groups.iter_mut().find(|(other, _)| ptr::eq(*other, info)) {
+            Some((_, names)) => names.push(name.clone()),
+            None => groups.push((info, vec![name.clone()])),
+        }
+    }
+    let mut statements = groups
+        .into_iter()
+        .map(|(info, names)| {
+            // The text only matches the statement if it still defines all its names.
+            let is_whole_statement = names.len() == info.names.len();
+            let names = if is_whole_statement {
+                &info.names
+            } else {
+                &names
+            };
+            let mut exprs = names
+                .iter()
+                .map(|name| program.left_side(name))
+                .collect::<Vec<_>>();
+            exprs.push(info.value.clone());
+            let source = info.source.clone().filter(|_| is_whole_statement);
+            (p::Statement::Assign(exprs), source)
+        })
+        .collect::<Vec<_>>();
+    // The sources belong to the first statements, so the ones without a source come last.
+    statements.sort_by_key(|(_, source)| source.is_none());
+    let (statements, sources): (Vec<_>, Vec<_>) = statements.into_iter().unzip();
+    let code = p::Code {
+        statements,
+        sources: sources.into_iter().flatten().collect(),
+    };
+    compile_code(code, program)
+        .map_err(|err| CompilationError::BadDependents(dependents.join(", "), Box::new(err)))
+}
+
 pub fn compile_into(code: p::Code, program: &mut Program) -> Result<(), CompilationError> {
+    let assigned = assigned_names(&code);
+    compile_code(code, program)?;
+    if program.is_live() {
+        recompile_dependents(assigned, program)?;
+    }
+    Ok(())
+}
+
+fn compile_code(code: p::Code, program: &mut Program) -> Result<(), CompilationError> {
     assert_no_duplicate_assignments(&code)?;
     insert_uninit_globals(&code, program)?;
-    for stmt in code.statements {
+    for (i, stmt) in code.statements.into_iter().enumerate() {
         match stmt {
             p::Statement::Assign(mut exprs) => {
                 let value = exprs.pop().unwrap();
                 compile_multi_assignment(program, &exprs, &value)?;
             }
             p::Statement::Evaluate(expr) => {
-                let expr = compile_expression(&expr, program, &GLOBAL_CONTEXT)?;
-                program.evaluate_later(expr);
+                let (expr, format) = match &expr {
+                    p::Expression::Convert(value, target) => match output_base(target, program)? {
+                        Some(base) => (&**value, Some(Format::Base(base))),
+                        None => (&expr, None),
+                    },
+                    _ => (&expr, None),
+                };
+                let expr = compile_expression(expr, program, &GLOBAL_CONTEXT)?;
+                program.evaluate_later(expr, format, code.sources.get(i).cloned());
             }
         }
     }
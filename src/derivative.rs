@@ -0,0 +1,444 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use malachite::num::arithmetic::traits::Reciprocal;
+use malachite::num::conversion::traits::IsInteger;
+use malachite::Rational;
+
+use crate::math;
+use crate::parse::{self as p, Expression};
+use crate::program::Program;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DerivativeError {
+    #[error("function not found: {0}")]
+    FunctionNotFound(String),
+    #[error("function {0:?} has no parameter {1:?}")]
+    ParamNotFound(String, String),
+    #[error("the built-in function {0:?} can't be differentiated")]
+    BuiltinFunction(String),
+    #[error("a power with a variable exponent can't be differentiated, because its derivative isn't rational")]
+    VariableExponent,
+    #[error("the derivative would replace {0:?}, which is already defined; delete it first")]
+    NameTaken(String),
+}
+
+// The derivative of a user function with respect to one of its parameters, as a new function.
+pub struct Derivative {
+    pub name: String,
+    pub params: Vec<String>,
+    pub code: Expression,
+}
+
+impl Derivative {
+    pub fn to_statement(&self) -> p::Statement {
+        let params = self.params.iter().cloned().map(Expression::Identifier);
+        p::Statement::Assign(vec![
+            Expression::Call(self.name.clone(), params.collect()),
+            self.code.clone(),
+        ])
+    }
+}
+
+impl fmt::Display for Derivative {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}({}) = {}",
+            self.name,
+            self.params.join(", "),
+            self.code
+        )
+    }
+}
+
+// f' for a function of one parameter, or f'x for the derivative of f with respect to its parameter x.
+fn derivative_name(function: &str, params: &[String], param: usize) -> String {
+    if params.len() == 1 {
+        format!("{function}'")
+    } else {
+        format!("{function}'{}", params[param])
+    }
+}
+
+fn number(n: impl Into<Rational>) -> Expression {
+    Expression::Number(n.into())
+}
+
+fn as_number(expr: &Expression) -> Option<&Rational> {
+    match expr {
+        Expression::Number(n) => Some(n),
+        _ => None,
+    }
+}
+
+fn is_zero(expr: &Expression) -> bool {
+    as_number(expr).is_some_and(|n| *n == 0)
+}
+
+fn is_one(expr: &Expression) -> bool {
+    as_number(expr).is_some_and(|n| *n == 1)
+}
+
+// These build expressions, simplifying them on the way.
+// They never simplify away an undefined result, except in the cases where the calculator itself does:
+// 0*x = 0 and x^0 = 1.
+
+fn neg(expr: Expression) -> Expression {
+    match expr {
+        Expression::Number(n) => Expression::Number(-n),
+        Expression::Neg(expr) => *expr,
+        expr => Expression::Neg(Box::new(expr)),
+    }
+}
+
+fn add(left: Expression, right: Expression) -> Expression {
+    match (left, right) {
+        (Expression::Number(n), Expression::Number(m)) => Expression::Number(n + m),
+        (left, right) if is_zero(&left) => right,
+        (left, right) if is_zero(&right) => left,
+        (left, Expression::Neg(right)) => sub(left, *right),
+        (left, right) => Expression::Add(Box::new(left), Box::new(right)),
+    }
+}
+
+fn sub(left: Expression, right: Expression) -> Expression {
+    match (left, right) {
+        (Expression::Number(n), Expression::Number(m)) => Expression::Number(n - m),
+        (left, right) if is_zero(&left) => neg(right),
+        (left, right) if is_zero(&right) => left,
+        (left, Expression::Neg(right)) => add(left, *right),
+        (left, right) => Expression::Sub(Box::new(left), Box::new(right)),
+    }
+}
+
+fn mul(left: Expression, right: Expression) -> Expression {
+    match (left, right) {
+        (Expression::Number(n), Expression::Number(m)) => Expression::Number(n * m),
+        (left, right) if is_zero(&left) || is_zero(&right) => number(0),
+        (left, right) if is_one(&left) => right,
+        (left, right) if is_one(&right) => left,
+        (Expression::Neg(left), right) => neg(mul(*left, right)),
+        (left, Expression::Neg(right)) => neg(mul(left, *right)),
+        // Numbers go first: x*2 = 2*x, 2*(3*x) = 6*x, and x*(2*y) = 2*(x*y).
+        (left, right @ Expression::Number(_)) => mul(right, left),
+        (Expression::Number(n), Expression::Mul(left, right))
+            if matches!(*left, Expression::Number(_)) =>
+        {
+            mul(mul(Expression::Number(n), *left), *right)
+        }
+        (left, Expression::Mul(n, right)) if matches!(*n, Expression::Number(_)) => {
+            mul(*n, mul(left, *right))
+        }
+        (Expression::Mul(n, left), right) if matches!(*n, Expression::Number(_)) => {
+            mul(*n, mul(*left, right))
+        }
+        (left, right) => Expression::Mul(Box::new(left), Box::new(right)),
+    }
+}
+
+fn div(left: Expression, right: Expression) -> Expression {
+    match (left, right) {
+        (Expression::Number(n), Expression::Number(m)) if m != 0 => Expression::Number(n / m),
+        (left, right) if is_one(&right) => left,
+        (Expression::Neg(left), right) => neg(div(*left, right)),
+        (left, Expression::Number(n)) if n != 0 => mul(Expression::Number(n.reciprocal()), left),
+        (left, right) => Expression::Div(Box::new(left), Box::new(right)),
+    }
+}
+
+fn pow(base: Expression, exponent: Expression) -> Expression {
+    match (base, exponent) {
+        (_, exponent) if is_zero(&exponent) => number(1),
+        (base, exponent) if is_one(&exponent) => base,
+        (Expression::Number(n), Expression::Number(m)) => match math::pow(n.clone(), m.clone()) {
+            Ok(power) => Expression::Number(power),
+            Err(_) => Expression::Pow(Box::new(number(n)), Box::new(number(m))),
+        },
+        (Expression::Pow(base, inner), Expression::Number(n))
+            if n.is_integer() && matches!(&*inner, Expression::Number(m) if m.is_integer()) =>
+        {
+            pow(*base, mul(*inner, Expression::Number(n)))
+        }
+        (base, exponent) => Expression::Pow(Box::new(base), Box::new(exponent)),
+    }
+}
+
+fn simplify(expr: &Expression) -> Expression {
+    let binary =
+        |left: &Expression, right: &Expression, op: fn(Expression, Expression) -> Expression| {
+            op(simplify(left), simplify(right))
+        };
+    match expr {
+        Expression::Pos(expr) => simplify(expr),
+        Expression::Neg(expr) => neg(simplify(expr)),
+        Expression::Add(left, right) => binary(left, right, add),
+        Expression::Sub(left, right) => binary(left, right, sub),
+        Expression::Mul(left, right) => binary(left, right, mul),
+        Expression::Div(left, right) => binary(left, right, div),
+        Expression::Pow(left, right) => binary(left, right, pow),
+        Expression::Call(name, args) => {
+            Expression::Call(name.clone(), args.iter().map(simplify).collect())
+        }
+        Expression::List(elements) => Expression::List(elements.iter().map(simplify).collect()),
+        Expression::Matrix(rows) => Expression::Matrix(
+            rows.iter()
+                .map(|row| row.iter().map(simplify).collect())
+                .collect(),
+        ),
+        Expression::Index(list, index) => {
+            Expression::Index(Box::new(simplify(list)), Box::new(simplify(index)))
+        }
+        Expression::Convert(value, units) => {
+            Expression::Convert(Box::new(simplify(value)), Box::new(simplify(units)))
+        }
+        Expression::Number(_) | Expression::Identifier(_) => expr.clone(),
+    }
+}
+
+struct Differentiator<'a> {
+    program: &'a Program,
+    // The derivatives that are needed, by function name and parameter index, with their names.
+    names: HashMap<(String, usize), String>,
+    to_differentiate: VecDeque<(String, usize)>,
+}
+
+impl Differentiator<'_> {
+    // Returns the name of the derivative, and schedules it to be computed if it's new.
+    // The name must not be defined already, so existing definitions are never replaced.
+    fn require(
+        &mut self,
+        function: &str,
+        params: &[String],
+        param: usize,
+    ) -> Result<String, DerivativeError> {
+        let key = (function.to_string(), param);
+        if let Some(name) = self.names.get(&key) {
+            return Ok(name.clone());
+        }
+        let name = derivative_name(function, params, param);
+        if self.program.names().any(|defined| defined == name) {
+            return Err(DerivativeError::NameTaken(name));
+        }
+        self.names.insert(key.clone(), name.clone());
+        self.to_differentiate.push_back(key);
+        Ok(name)
+    }
+
+    fn call(
+        &mut self,
+        name: &str,
+        args: &[Expression],
+        var: &str,
+    ) -> Result<Expression, DerivativeError> {
+        let arg_derivatives = args
+            .iter()
+            .map(|arg| self.derivative(arg, var))
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(function) = self.program.get_function(name) {
+            let params = function.get().expect("uninitialized function").params();
+            // The chain rule: d/dx f(u, v) = f'u(u, v)*u' + f'v(u, v)*v'
+            let mut result = number(0);
+            for (i, arg_derivative) in arg_derivatives.into_iter().enumerate() {
+                if is_zero(&arg_derivative) {
+                    continue;
+                }
+                let derivative_name = self.require(name, params, i)?;
+                let outer = Expression::Call(derivative_name, args.to_vec());
+                result = add(result, mul(outer, arg_derivative));
+            }
+            return Ok(result);
+        }
+        if self.program.get_native(name).is_some() {
+            if arg_derivatives.iter().all(is_zero) {
+                return Ok(number(0));
+            }
+            return match (name, &arg_derivatives[..]) {
+                ("if", [_, x, y]) => Ok(Expression::Call(
+                    name.to_string(),
+                    vec![args[0].clone(), x.clone(), y.clone()],
+                )),
+                _ => Err(DerivativeError::BuiltinFunction(name.to_string())),
+            };
+        }
+        // A list indexed like a function.
+        let list = Expression::Identifier(name.to_string());
+        self.derivative(
+            &Expression::Index(Box::new(list), Box::new(args[0].clone())),
+            var,
+        )
+    }
+
+    fn derivative(&mut self, expr: &Expression, var: &str) -> Result<Expression, DerivativeError> {
+        if let Expression::Call(name, args) = expr {
+            return self.call(name, args, var);
+        }
+        let mut d = |expr: &Expression| self.derivative(expr, var);
+        Ok(match expr {
+            Expression::Number(_) => number(0),
+            Expression::Identifier(name) => number(if name == var { 1 } else { 0 }),
+            Expression::Pos(expr) => d(expr)?,
+            Expression::Neg(expr) => neg(d(expr)?),
+            Expression::Add(left, right) => add(d(left)?, d(right)?),
+            Expression::Sub(left, right) => sub(d(left)?, d(right)?),
+            Expression::Mul(left, right) => add(
+                mul(d(left)?, (**right).clone()),
+                mul((**left).clone(), d(right)?),
+            ),
+            Expression::Div(left, right) => {
+                let left_derivative = d(left)?;
+                let right_derivative = d(right)?;
+                if is_zero(&right_derivative) {
+                    div(left_derivative, (**right).clone())
+                } else {
+                    div(
+                        sub(
+                            mul(left_derivative, (**right).clone()),
+                            mul((**left).clone(), right_derivative),
+                        ),
+                        pow((**right).clone(), number(2)),
+                    )
+                }
+            }
+            Expression::Pow(base, exponent) => {
+                if !is_zero(&d(exponent)?) {
+                    return Err(DerivativeError::VariableExponent);
+                }
+                // d/dx u^n = n*u^(n - 1)*u'
+                let base_derivative = d(base)?;
+                mul(
+                    mul(
+                        (**exponent).clone(),
+                        pow((**base).clone(), sub((**exponent).clone(), number(1))),
+                    ),
+                    base_derivative,
+                )
+            }
+            Expression::Call(..) => unreachable!(),
+            Expression::List(elements) => {
+                Expression::List(elements.iter().map(d).collect::<Result<_, _>>()?)
+            }
+            Expression::Matrix(rows) => Expression::Matrix(
+                rows.iter()
+                    .map(|row| row.iter().map(&mut d).collect::<Result<_, _>>())
+                    .collect::<Result<_, _>>()?,
+            ),
+            Expression::Index(list, index) => match d(list)? {
+                // Every element of the list has the same derivative.
+                derivative @ Expression::Number(_) => derivative,
+                derivative => Expression::Index(Box::new(derivative), index.clone()),
+            },
+            // Conversion to other units is linear.
+            Expression::Convert(value, units) => match d(value)? {
+                zero if is_zero(&zero) => zero,
+                derivative => Expression::Convert(Box::new(derivative), units.clone()),
+            },
+        })
+    }
+}
+
+// Differentiates a user function with respect to one of its parameters (by default the first one).
+// Returns the definitions of the derivative, and of the derivatives of the functions it calls.
+pub fn differentiate(
+    program: &Program,
+    function: &str,
+    param: Option<&str>,
+) -> Result<Vec<Derivative>, DerivativeError> {
+    let Some(params) = program
+        .get_function(function)
+        .and_then(|function| function.get())
+        .map(|function| function.params())
+    else {
+        return Err(DerivativeError::FunctionNotFound(function.to_string()));
+    };
+    let param = match param {
+        Some(param) => params.iter().position(|p| p == param).ok_or_else(|| {
+            DerivativeError::ParamNotFound(function.to_string(), param.to_string())
+        })?,
+        None => 0,
+    };
+    let mut differentiator = Differentiator {
+        program,
+        names: HashMap::new(),
+        to_differentiate: VecDeque::new(),
+    };
+    differentiator.require(function, params, param)?;
+    let mut derivatives = vec![];
+    while let Some((function, param)) = differentiator.to_differentiate.pop_front() {
+        let function_ref = program.get_function(&function).unwrap().get().unwrap();
+        let params = function_ref.params();
+        let code = differentiator.derivative(&simplify(function_ref.source()), &params[param])?;
+        derivatives.push(Derivative {
+            name: differentiator.names[&(function, param)].clone(),
+            params: params.to_vec(),
+            code,
+        });
+    }
+    Ok(derivatives)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compile;
+
+    fn assert_derivatives(code: &str, function: &str, expected: &[&str]) {
+        let program = compile::compile(p::parse(code).unwrap()).unwrap();
+        let derivatives = differentiate(&program, function, None).unwrap();
+        let found: Vec<String> = derivatives.iter().map(|d| d.to_string()).collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn derivative_test() {
+        assert_derivatives("f(x) = 3*x^2 - x + 7", "f", &["f'(x) = 6*x - 1"]);
+        assert_derivatives("f(x) = x^(1/2)", "f", &["f'(x) = 1/2*x^(-1/2)"]);
+        assert_derivatives("f(x) = 1/x", "f", &["f'(x) = -1/x^2"]);
+        assert_derivatives("f(x) = (x^2)^3", "f", &["f'(x) = 6*x^5"]);
+        // Not folded into x^1, which is defined where x^(1/2) isn't.
+        assert_derivatives("f(x) = (x^(1/2))^2", "f", &["f'(x) = x^(1/2)*x^(-1/2)"]);
+        assert_derivatives("f(x) = x/(x + 1)", "f", &["f'(x) = (x + 1 - x)/(x + 1)^2"]);
+        assert_derivatives(
+            "g(y) = y^3\nf(x) = g(2*x)",
+            "f",
+            &["f'(x) = 2*g'(2*x)", "g'(y) = 3*y^2"],
+        );
+        assert_derivatives(
+            "f(n) = if(n, n*f(n - 1), 1)",
+            "f",
+            &["f'(n) = if(n, f(n - 1) + n*f'(n - 1), 0)"],
+        );
+    }
+
+    #[test]
+    fn error_test() {
+        let program = compile::compile(p::parse("f(x) = 2^x\ng(x) = floor(x)").unwrap()).unwrap();
+        assert!(matches!(
+            differentiate(&program, "f", None),
+            Err(DerivativeError::VariableExponent)
+        ));
+        assert!(matches!(
+            differentiate(&program, "g", None),
+            Err(DerivativeError::BuiltinFunction(_))
+        ));
+        assert!(matches!(
+            differentiate(&program, "f", Some("y")),
+            Err(DerivativeError::ParamNotFound(..))
+        ));
+    }
+
+    #[test]
+    fn name_taken_test() {
+        let code = "f'(x) = x + 100\nf(x) = f'(x)*2\nh'(y) = 1\nh(y) = y^3\ng(x) = h(2*x)";
+        let program = compile::compile(p::parse(code).unwrap()).unwrap();
+        assert!(matches!(
+            differentiate(&program, "f", None),
+            Err(DerivativeError::NameTaken(name)) if name == "f'"
+        ));
+        // Derivatives needed by the chain rule aren't replaced either.
+        assert!(matches!(
+            differentiate(&program, "g", None),
+            Err(DerivativeError::NameTaken(name)) if name == "h'"
+        ));
+    }
+}
@@ -1,7 +1,10 @@
 use crate::bool_gen::BoolGen;
 use crate::ctrlc_handler::{CtrlCError, CtrlCHandler};
+use crate::json;
 use crate::math::format::{Format, FormattedValue};
 use crate::math::Value;
+use crate::parse::Source;
+use std::fmt::Display;
 use std::io::{self, BufRead, Write};
 
 pub struct EvaluationEnvironemnt {
@@ -27,14 +30,46 @@ impl EvaluationEnvironemnt {
         self.ctrlc_handler.catch()
     }
 }
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputMode {
+    #[default]
+    Text,
+    // One JSON object per line for each result, and for each error on the error output.
+    Json,
+}
+
+// What went wrong, for machine-readable output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    Parse,
+    Compilation,
+    Command,
+    Load, // A library or an input file that couldn't be run, which stops recalc.
+}
+
+impl ErrorKind {
+    fn code(self) -> &'static str {
+        match self {
+            ErrorKind::Parse => "parse",
+            ErrorKind::Compilation => "compilation",
+            ErrorKind::Command => "command",
+            ErrorKind::Load => "load",
+        }
+    }
+}
+
 pub struct IoOptions<'a> {
     pub input: Box<dyn BufRead + 'a>,
     pub output: Box<dyn Write + 'a>,
     pub error_output: Box<dyn Write + 'a>,
     pub output_format: Format,
+    pub output_mode: OutputMode,
     pub are_errors_fatal: bool,
     pub suggest_help: bool,
     pub show_welcome_message: bool,
+    pub show_result_numbers: bool,
+    pub use_line_editor: bool, // Read from the terminal with line editing, instead of from input.
 }
 
 impl<'a> Default for IoOptions<'a> {
@@ -44,9 +79,12 @@ impl<'a> Default for IoOptions<'a> {
             output: Box::new(std::io::stdout()),
             error_output: Box::new(std::io::stderr()),
             output_format: Format::default(),
+            output_mode: OutputMode::default(),
             are_errors_fatal: false,
             suggest_help: false,
             show_welcome_message: true,
+            show_result_numbers: false,
+            use_line_editor: false,
         }
     }
 }
@@ -72,8 +110,49 @@ impl<'a> Environment<'a> {
 
     pub fn output_value(&mut self, value: &Value) -> Result<(), io::Error> {
         let fmt = self.io_options.output_format;
-        writeln!(self.output(), "{}", FormattedValue(fmt, value))?;
-        Ok(())
+        match self.io_options.output_mode {
+            OutputMode::Text => writeln!(self.output(), "{}", FormattedValue(fmt, value)),
+            OutputMode::Json => {
+                let result = json::result(value, fmt, None, None);
+                writeln!(self.output(), "{result}")
+            }
+        }
+    }
+
+    // Outputs the value of a statement, which was parsed from the source if it's given,
+    // and can be referred to as $number.
+    pub fn output_result(
+        &mut self,
+        value: &Value,
+        fmt: Format,
+        source: Option<&Source>,
+        number: usize,
+    ) -> Result<(), io::Error> {
+        match self.io_options.output_mode {
+            OutputMode::Text if self.io_options.show_result_numbers => {
+                writeln!(self.output(), "${number} = {}", FormattedValue(fmt, value))
+            }
+            OutputMode::Text => writeln!(self.output(), "{}", FormattedValue(fmt, value)),
+            OutputMode::Json => {
+                let result = json::result(value, fmt, source, Some(number));
+                writeln!(self.output(), "{result}")
+            }
+        }
+    }
+
+    pub fn output_error(
+        &mut self,
+        kind: ErrorKind,
+        message: impl Display,
+        line: Option<usize>,
+    ) -> Result<(), io::Error> {
+        match self.io_options.output_mode {
+            OutputMode::Text => writeln!(self.error_output(), "{message}"),
+            OutputMode::Json => {
+                let error = json::error(kind.code(), message, line);
+                writeln!(self.error_output(), "{error}")
+            }
+        }
     }
 
     pub fn init_ctrlc_handler(&mut self) {
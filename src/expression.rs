@@ -1,9 +1,13 @@
 use crate::ctrlc_handler::CtrlCError;
 use crate::environment::EvaluationEnvironemnt;
 use crate::math::{self, Value};
-use crate::program::{LazyExpression, WeakConstant, WeakFunction};
+use crate::native::{Arguments, FunctionRef, NativeFunction, Unevaluated};
+use crate::program::{
+    Function, LazyExpression, RcConstant, RcFunction, WeakConstant, WeakFunction,
+};
 use malachite::num::basic::traits::One;
 use malachite::Rational;
+use std::cell::OnceCell;
 use std::mem::{self, ManuallyDrop};
 use std::ptr;
 use std::rc::Rc;
@@ -20,7 +24,12 @@ pub enum Expression {
     Div(Box<Expression>, Box<Expression>),
     Pow(Box<Expression>, Box<Expression>),
     Call(WeakFunction, Vec<Expression>),
+    CallNative(NativeFunction, Vec<Expression>),
+    List(Vec<Expression>),
+    Matrix(usize, Vec<Expression>), // The number of columns and the entries, row by row.
+    Index(Box<Expression>, Box<Expression>),
     ArgumentIndex(usize),
+    Function(FunctionRef), // Only as an argument of a native function.
 }
 
 enum SimplifyStepResult<'a> {
@@ -65,7 +74,8 @@ impl Drop for Expression {
                 | Sub(left, right)
                 | Mul(left, right)
                 | Div(left, right)
-                | Pow(left, right) => unsafe {
+                | Pow(left, right)
+                | Index(left, right) => unsafe {
                     let left = ptr::read(left as *mut Box<Expression>);
                     to_drop.push(*left);
                     let right = ptr::read(right as *mut Box<Expression>);
@@ -80,36 +90,179 @@ impl Drop for Expression {
                         to_drop.push(arg);
                     }
                 }
+                CallNative(function, args) => {
+                    unsafe {
+                        ptr::drop_in_place(function as *mut NativeFunction);
+                    }
+                    let args = mem::take(args);
+                    for arg in args {
+                        to_drop.push(arg);
+                    }
+                }
+                List(args) | Matrix(_, args) => {
+                    let args = mem::take(args);
+                    for arg in args {
+                        to_drop.push(arg);
+                    }
+                }
+                Function(function) => unsafe {
+                    ptr::drop_in_place(function as *mut FunctionRef);
+                },
                 ArgumentIndex(_) => {}
             }
         }
     }
 }
 
+// If all the expressions are evaluated, returns their values.
+// Otherwise, returns the next step of simplifying them.
+// If one of the expressions is undefined, the step is to replace the whole expression with it.
+fn simplify_all<'a>(
+    exprs: &'a mut [Expression],
+    env: &mut EvaluationEnvironemnt,
+) -> Result<Vec<math::Value>, SimplifyStepResult<'a>> {
+    let mut unevaluated = None;
+    for (i, expr) in exprs.iter().enumerate() {
+        match expr.value_if_found() {
+            Some(u) if u.is_undefined() => {
+                return Err(SimplifyStepResult::ReplaceWith(Expression::Value(
+                    u.clone(),
+                )))
+            }
+            Some(_) => {}
+            None if unevaluated.is_none() || env.gen_bool() => unevaluated = Some(i),
+            None => {}
+        }
+    }
+    if let Some(i) = unevaluated {
+        return Err(SimplifyStepResult::SimplifyPart(&mut exprs[i]));
+    }
+    Ok(exprs
+        .iter_mut()
+        .map(|expr| mem::take(expr.value_if_found_mut().unwrap()))
+        .collect())
+}
+
+// Whether an operation is decided by found satisfying the test, without finding other,
+// because other is known to be a scalar.
+fn can_skip(found: &Expression, other: &Expression, test: fn(&Value) -> bool) -> bool {
+    found.value_if_found().is_some_and(test)
+        && other.value_if_found().is_none()
+        && !ShapeAnalysis::may_be_compound(other)
+}
+
+// The largest number of subexpressions looked at to find out if an expression may be compound.
+const MAX_SHAPE_STEPS: usize = 1000;
+
+// Finds out, without evaluating an expression, whether its value may be compound:
+// a list, a matrix, a residue or a quantity, which keep their shape when multiplied by zero
+// or raised to the 0th, unlike scalars. Functions are followed into their code, and a function
+// or constant that's reached again while it's being looked at is an infinite loop,
+// which is undefined and so a scalar. Native functions may return anything.
+#[derive(Default)]
+struct ShapeAnalysis {
+    constants: Vec<*const LazyExpression>,
+    calls: Vec<(*const OnceCell<Function>, Vec<bool>)>, // With which arguments may be compound.
+    steps: usize,
+}
+
+impl ShapeAnalysis {
+    fn may_be_compound(expr: &Expression) -> bool {
+        Self::default().expression(expr, &[])
+    }
+
+    // args says which arguments of the function whose code this is may be compound.
+    fn expression(&mut self, expr: &Expression, args: &[bool]) -> bool {
+        use Expression::*;
+        self.steps += 1;
+        if self.steps > MAX_SHAPE_STEPS {
+            return true;
+        }
+        match expr {
+            Value(value) => !value.is_scalar(),
+            Argument(arg) => self.lazy(arg),
+            Constant(con) => self.lazy(&con.upgrade()),
+            &ArgumentIndex(i) => args[i],
+            Neg(operand) => self.expression(operand, args),
+            Add(left, right)
+            | Sub(left, right)
+            | Mul(left, right)
+            | Div(left, right)
+            | Pow(left, right) => self.expression(left, args) || self.expression(right, args),
+            Call(function, call_args) => {
+                let call_args = call_args
+                    .iter()
+                    .map(|arg| self.expression(arg, args))
+                    .collect();
+                self.call(&function.upgrade(), call_args)
+            }
+            CallNative(..) | List(_) | Matrix(..) | Index(..) | Function(_) => true,
+        }
+    }
+
+    fn lazy(&mut self, lazy: &RcConstant) -> bool {
+        if let Some(value) = lazy.value_if_found() {
+            return !value.is_scalar();
+        }
+        let ptr = Rc::as_ptr(lazy);
+        if self.constants.contains(&ptr) {
+            return false;
+        }
+        // If it's being simplified, this expression is part of it, so it's an infinite loop.
+        let Some(expr) = lazy.try_expression() else {
+            return false;
+        };
+        self.constants.push(ptr);
+        let result = self.expression(&expr, &[]);
+        self.constants.pop();
+        result
+    }
+
+    fn call(&mut self, function: &RcFunction, args: Vec<bool>) -> bool {
+        let key = (Rc::as_ptr(function), args);
+        if self.calls.contains(&key) {
+            return false;
+        }
+        let code = function.get().expect("uninitialized function").code();
+        self.calls.push(key);
+        let result = self.expression(code, &self.calls.last().unwrap().1.clone());
+        self.calls.pop();
+        result
+    }
+}
+
 impl Expression {
     fn has_child_expressions(&self) -> bool {
         use Expression::*;
         match self {
-            Value(_) | Argument(_) | Constant(_) | ArgumentIndex(_) => false,
-            Neg(_) | Add(_, _) | Sub(_, _) | Mul(_, _) | Div(_, _) | Pow(_, _) => true,
-            Call(_, exprs) => !exprs.is_empty(),
+            Value(_) | Argument(_) | Constant(_) | ArgumentIndex(_) | Function(_) => false,
+            Neg(_) | Add(_, _) | Sub(_, _) | Mul(_, _) | Div(_, _) | Pow(_, _) | Index(_, _) => {
+                true
+            }
+            Call(_, exprs) | CallNative(_, exprs) | List(exprs) | Matrix(_, exprs) => {
+                !exprs.is_empty()
+            }
         }
     }
 
     pub fn substitute_args(&mut self, args: &[Rc<LazyExpression>]) {
         use Expression::*;
         match self {
-            Value(_) | Argument(_) | Constant(_) => {}
+            Value(_) | Argument(_) | Constant(_) | Function(_) => {}
             Neg(expr) => expr.substitute_args(args),
             Add(left, right)
             | Sub(left, right)
             | Mul(left, right)
             | Div(left, right)
-            | Pow(left, right) => {
+            | Pow(left, right)
+            | Index(left, right) => {
                 left.substitute_args(args);
                 right.substitute_args(args);
             }
-            Call(_, inner_args) => {
+            Call(_, inner_args)
+            | CallNative(_, inner_args)
+            | List(inner_args)
+            | Matrix(_, inner_args) => {
                 for arg in inner_args {
                     arg.substitute_args(args);
                 }
@@ -182,14 +335,21 @@ impl Expression {
                 (None, None) if env.gen_bool() => SimplifyPart(left),
                 (None, None) => SimplifyPart(right),
             },
-            Mul(left, right) => match (left.value_if_found_mut(), right.value_if_found_mut()) {
-                (Some(x), _) | (_, Some(x)) if x.is_zero() => ReplaceWith(Value(mem::take(x))),
-                (Some(n), Some(m)) => ReplaceWith(Value(mem::take(n) * m)),
-                (Some(_), None) => SimplifyPart(right),
-                (None, Some(_)) => SimplifyPart(left),
-                (None, None) if env.gen_bool() => SimplifyPart(left),
-                (None, None) => SimplifyPart(right),
-            },
+            Mul(left, right) => {
+                // A zero times a scalar is zero, even if the scalar is undefined,
+                // but a zero times a list or a matrix has its shape, so it has to be found.
+                let zero_left = can_skip(left, right, math::Value::is_zero);
+                let zero_right = can_skip(right, left, math::Value::is_zero);
+                match (left.value_if_found_mut(), right.value_if_found_mut()) {
+                    (Some(n), Some(m)) => ReplaceWith(Value(mem::take(n) * m)),
+                    (Some(x), None) if zero_left => ReplaceWith(Value(mem::take(x))),
+                    (None, Some(x)) if zero_right => ReplaceWith(Value(mem::take(x))),
+                    (Some(_), None) => SimplifyPart(right),
+                    (None, Some(_)) => SimplifyPart(left),
+                    (None, None) if env.gen_bool() => SimplifyPart(left),
+                    (None, None) => SimplifyPart(right),
+                }
+            }
 
             Div(left, right) => match (left.value_if_found_mut(), right.value_if_found_mut()) {
                 (Some(u), None) | (None, Some(u)) if u.is_undefined() => {
@@ -202,17 +362,22 @@ impl Expression {
                 (None, None) => SimplifyPart(right),
             },
 
-            Pow(left, right) => match (left.value_if_found_mut(), right.value_if_found_mut()) {
-                (Some(n), Some(m)) => ReplaceWith(Value(mem::take(n).pow(m))),
-                (Some(one), _) if one.is_one() => ReplaceWith(Value(mem::take(one))),
-                (_, Some(z)) if z.is_zero() => {
-                    ReplaceWith(Value(math::Value::Number(Rational::ONE)))
+            Pow(left, right) => {
+                // Like zero products, these only skip finding the other side if it's a scalar.
+                let one_base = can_skip(left, right, math::Value::is_one);
+                let zero_exponent = can_skip(right, left, math::Value::is_zero);
+                match (left.value_if_found_mut(), right.value_if_found_mut()) {
+                    (Some(n), Some(m)) => ReplaceWith(Value(mem::take(n).pow(m))),
+                    (Some(one), None) if one_base => ReplaceWith(Value(mem::take(one))),
+                    (None, Some(_)) if zero_exponent => {
+                        ReplaceWith(Value(math::Value::Number(Rational::ONE)))
+                    }
+                    (Some(_), None) => SimplifyPart(right),
+                    (None, Some(_)) => SimplifyPart(left),
+                    (None, None) if env.gen_bool() => SimplifyPart(left),
+                    (None, None) => SimplifyPart(right),
                 }
-                (Some(_), None) => SimplifyPart(right),
-                (None, Some(_)) => SimplifyPart(left),
-                (None, None) if env.gen_bool() => SimplifyPart(left),
-                (None, None) => SimplifyPart(right),
-            },
+            }
             Call(func, ref mut args) => {
                 let args = mem::take(args)
                     .into_iter()
@@ -220,6 +385,63 @@ impl Expression {
                     .collect::<Vec<_>>();
                 ReplaceWith(func.call(&args))
             }
+            CallNative(NativeFunction::Strict(function), args) => match simplify_all(args, env) {
+                Ok(args) => ReplaceWith(Value(function(&args))),
+                Err(step) => step,
+            },
+            CallNative(NativeFunction::Lazy(function), args) => {
+                match function(&Arguments::new(args)) {
+                    Ok(value) => ReplaceWith(Value(value)),
+                    Err(Unevaluated::Argument(i)) => SimplifyPart(&mut args[i]),
+                    Err(Unevaluated::Element(element)) => {
+                        SimplifyConstant(WeakConstant::from(&element))
+                    }
+                }
+            }
+            CallNative(NativeFunction::Rewrite(function), args) => {
+                match function(&Arguments::new(args)) {
+                    Ok(expr) => ReplaceWith(expr),
+                    Err(Unevaluated::Argument(i)) => SimplifyPart(&mut args[i]),
+                    Err(Unevaluated::Element(element)) => {
+                        SimplifyConstant(WeakConstant::from(&element))
+                    }
+                }
+            }
+            Matrix(cols, entries) => match simplify_all(entries, env) {
+                Ok(entries) => ReplaceWith(Value(
+                    entries
+                        .into_iter()
+                        .map(|entry| match entry {
+                            math::Value::Number(n) => Ok(n),
+                            _ => Err(math::Undefined::TypeMismatch),
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                        .map(|entries| math::Matrix::new(entries.len() / *cols, *cols, entries))
+                        .into(),
+                )),
+                Err(step) => step,
+            },
+            List(elements) => ReplaceWith(Value(math::Value::List(math::List::new(mem::take(
+                elements,
+            ))))),
+            Index(list, index) => match (list.value_if_found_mut(), index.value_if_found_mut()) {
+                (Some(u), _) | (_, Some(u)) if u.is_undefined() => ReplaceWith(Value(mem::take(u))),
+                (Some(math::Value::List(list)), Some(index)) => match list.get(index) {
+                    Ok(element) => ReplaceWith(Argument(Rc::clone(element))),
+                    Err(u) => ReplaceWith(Value(math::Value::Undefined(u))),
+                },
+                (Some(_), Some(_)) => {
+                    ReplaceWith(Value(math::Value::Undefined(math::Undefined::TypeMismatch)))
+                }
+                (Some(_), None) => SimplifyPart(index),
+                (None, Some(_)) => SimplifyPart(list),
+                (None, None) if env.gen_bool() => SimplifyPart(list),
+                (None, None) => SimplifyPart(index),
+            },
+            // The native function didn't expect a function.
+            Function(_) => {
+                ReplaceWith(Value(math::Value::Undefined(math::Undefined::TypeMismatch)))
+            }
             ArgumentIndex(_) => panic!("argument was not substituted"),
         }
     }
@@ -0,0 +1,208 @@
+// Machine-readable output, one JSON object per line.
+
+use crate::math::format::{Format, FormattedValue};
+use crate::math::Value;
+use crate::parse::Source;
+use malachite::num::conversion::traits::ToSci;
+use malachite::Rational;
+use std::fmt::{self, Display};
+
+pub enum Json {
+    Null,
+    Integer(usize),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl Json {
+    fn string(s: impl Display) -> Self {
+        Json::String(s.to_string())
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Integer(n) => write!(f, "{n}"),
+            Json::String(s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\r' => write!(f, "\\r")?,
+                        '\t' => write!(f, "\\t")?,
+                        c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                        c => write!(f, "{c}")?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Json::Array(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{element}")?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{value}", Json::string(name))?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn number_fields(n: &Rational) -> Vec<(&'static str, Json)> {
+    let sign = if *n < 0 { "-" } else { "" };
+    vec![
+        (
+            "numerator",
+            Json::string(format!("{sign}{}", n.numerator_ref())),
+        ),
+        ("denominator", Json::string(n.denominator_ref())),
+        ("decimal", Json::string(n.to_sci())),
+    ]
+}
+
+// The fields that describe a value, starting with its type.
+fn value_fields(value: &Value) -> Vec<(&'static str, Json)> {
+    let value_json = |value: &Value| Json::Object(value_fields(value));
+    let number_json = |n: &Rational| value_json(&Value::Number(n.clone()));
+    let (type_name, mut fields) = match value {
+        Value::Number(n) => ("number", number_fields(n)),
+        Value::List(l) => {
+            let elements = l
+                .iter()
+                .map(|element| match element.value_if_found() {
+                    Some(value) => value_json(value),
+                    None => Json::Null,
+                })
+                .collect();
+            ("list", vec![("elements", Json::Array(elements))])
+        }
+        Value::Matrix(m) => {
+            let rows = (0..m.rows())
+                .map(|i| Json::Array(m.row(i).iter().map(number_json).collect()))
+                .collect();
+            ("matrix", vec![("rows", Json::Array(rows))])
+        }
+        Value::Mod(r) => (
+            "residue",
+            vec![
+                ("value", Json::string(r.value())),
+                ("modulus", Json::string(r.modulus())),
+            ],
+        ),
+        Value::Poly(p) => {
+            // From the lowest degree up.
+            let coefficients = p.coefficients().iter().map(number_json).collect();
+            (
+                "polynomial",
+                vec![("coefficients", Json::Array(coefficients))],
+            )
+        }
+        Value::Quantity(q) => {
+            let mut fields = number_fields(q.value());
+            fields.push(("units", Json::string(q.units())));
+            ("quantity", fields)
+        }
+        Value::Undefined(u) => (
+            "undefined",
+            vec![
+                ("undefined", Json::String(u.code().to_string())),
+                ("message", Json::string(u)),
+            ],
+        ),
+    };
+    fields.insert(0, ("type", Json::String(type_name.to_string())));
+    fields
+}
+
+fn source_fields(source: Option<&Source>) -> Vec<(&'static str, Json)> {
+    vec![
+        ("line", source.map_or(Json::Null, |s| Json::Integer(s.line))),
+        (
+            "source",
+            source.map_or(Json::Null, |s| Json::string(&s.text)),
+        ),
+    ]
+}
+
+// The result of evaluating a statement, e.g. {"result":1,"line":1,"source":"4/3","type":"number","numerator":"4",
+// "denominator":"3","decimal":"1.333333333333333","text":"1.333333333333333"}
+pub fn result(
+    value: &Value,
+    format: Format,
+    source: Option<&Source>,
+    number: Option<usize>,
+) -> Json {
+    let mut fields = vec![("result", number.map_or(Json::Null, Json::Integer))];
+    fields.extend(source_fields(source));
+    fields.extend(value_fields(value));
+    fields.push(("text", Json::string(FormattedValue(format, value))));
+    Json::Object(fields)
+}
+
+// An error, e.g. {"error":"parse","message":"Unexpected character: ')'","line":2}
+pub fn error(kind: &str, message: impl Display, line: Option<usize>) -> Json {
+    Json::Object(vec![
+        ("error", Json::String(kind.to_string())),
+        ("message", Json::string(message)),
+        ("line", line.map_or(Json::Null, Json::Integer)),
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::math::{Matrix, Undefined};
+
+    #[test]
+    fn result_test() {
+        let source = Source {
+            line: 3,
+            text: "\"4\"/3".to_string(),
+            doc: None,
+            file: None,
+        };
+        let four_thirds = Value::Number(Rational::from_signeds(-4, 3));
+        assert_eq!(
+            result(&four_thirds, Format::Fraction, Some(&source), Some(2)).to_string(),
+            "{\"result\":2,\"line\":3,\"source\":\"\\\"4\\\"/3\",\"type\":\"number\",\"numerator\":\"-4\",\
+            \"denominator\":\"3\",\"decimal\":\"-1.333333333333333\",\"text\":\"-4/3\"}"
+        );
+        assert_eq!(
+            result(&Value::Undefined(Undefined::ZeroOverZero), Format::Fraction, None, None).to_string(),
+            "{\"result\":null,\"line\":null,\"source\":null,\"type\":\"undefined\",\"undefined\":\"zero_over_zero\",\
+            \"message\":\"Undefined result: zero divided by zero\",\
+            \"text\":\"Undefined result: zero divided by zero\"}"
+        );
+        let matrix = Value::Matrix(Matrix::new(1, 1, vec![Rational::from(2)]));
+        assert_eq!(
+            Json::Object(value_fields(&matrix)).to_string(),
+            "{\"type\":\"matrix\",\"rows\":[[{\"type\":\"number\",\"numerator\":\"2\",\
+            \"denominator\":\"1\",\"decimal\":\"2\"}]]}"
+        );
+    }
+
+    #[test]
+    fn error_test() {
+        assert_eq!(
+            error("parse", "Unexpected character: '\\t'", Some(2)).to_string(),
+            "{\"error\":\"parse\",\"message\":\"Unexpected character: '\\\\t'\",\"line\":2}"
+        );
+    }
+}
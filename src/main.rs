@@ -1,9 +1,13 @@
 mod bool_gen;
+mod builtin;
 mod compile;
 mod ctrlc_handler;
+mod derivative;
 mod environment;
 mod expression;
+mod json;
 mod math;
+mod native;
 mod parse;
 mod program;
 mod run;
@@ -13,8 +17,9 @@ mod test;
 use crate::math::format::Format;
 use crate::program::Program;
 use clap::Parser;
-use environment::Environment;
+use environment::{Environment, ErrorKind, OutputMode};
 use std::error::Error;
+use std::io::{self, IsTerminal};
 use std::path::PathBuf;
 use std::process::ExitCode;
 
@@ -23,11 +28,20 @@ struct Args {
     /// Input file
     input: Option<PathBuf>,
     /// The format in which to display output
-    #[arg(value_enum, default_value_t, short, long)]
+    #[arg(default_value_t, short, long)]
     format: Format,
+    /// Whether to output plain text, or one JSON object per result and per error
+    #[arg(value_enum, default_value_t, short, long)]
+    output: OutputMode,
     /// Load a library
     #[arg(short, long)]
     load: Vec<PathBuf>,
+    /// When a definition is replaced, recompile the definitions that depend on it
+    #[arg(long)]
+    live: bool,
+    /// The maximum size in bits of the result of a power; larger powers are undefined
+    #[arg(long, default_value_t = math::DEFAULT_MAX_BITS)]
+    max_bits: u64,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -36,28 +50,33 @@ struct FailedToLoadLibrary(PathBuf, Box<dyn Error>);
 
 fn main() -> ExitCode {
     let args = Args::parse();
-    if let Err(err) = run(&args) {
-        println!("{}", err);
+    let mut env = Environment::default();
+    env.io_options.output_mode = args.output;
+    if let Err(err) = run(&args, &mut env) {
+        // Reported like the errors of statements, so in JSON mode every line of output is JSON.
+        let _ = env.output_error(ErrorKind::Load, err, None);
         ExitCode::FAILURE
     } else {
         ExitCode::SUCCESS
     }
 }
 
-fn run(args: &Args) -> Result<(), Box<dyn Error>> {
-    let mut env = Environment::default();
+fn run(args: &Args, env: &mut Environment) -> Result<(), Box<dyn Error>> {
     env.io_options.output_format = args.format;
+    math::set_max_bits(args.max_bits);
     env.init_ctrlc_handler();
     let mut program = Program::new();
+    program.set_live(args.live);
     env.io_options.are_errors_fatal = true;
     for lib in &args.load {
-        run::run_file(lib, &mut program, &mut env)
+        run::run_file(lib, &mut program, env)
             .map_err(|err| FailedToLoadLibrary(lib.clone(), err))?;
     }
     env.io_options.are_errors_fatal = false;
     if let Some(input) = &args.input {
-        run::run_file(input, &mut program, &mut env)
+        run::run_file(input, &mut program, env)
     } else {
-        run::repl(&mut program, &mut env)
+        env.io_options.use_line_editor = io::stdin().is_terminal() && io::stdout().is_terminal();
+        run::repl(&mut program, env)
     }
 }
@@ -1,6 +1,19 @@
 pub mod format;
+pub mod list;
+pub mod markup;
+mod matrix;
+mod modular;
+pub mod number_theory;
+mod polynomial;
 mod pow;
+pub mod solve;
+mod units;
 mod value;
 
-pub use pow::pow;
-pub use value::Value;
+pub use list::List;
+pub use matrix::Matrix;
+pub use modular::Residue;
+pub use polynomial::Polynomial;
+pub use pow::{max_bits, pow, set_max_bits, DEFAULT_MAX_BITS};
+pub use units::{units, Quantity};
+pub use value::{Undefined, Value};
@@ -1,50 +1,301 @@
+use crate::math::markup::{Markup, Notation};
+use crate::math::number_theory as nt;
 use crate::math::Value;
-use malachite::num::arithmetic::traits::Abs;
+use malachite::num::arithmetic::traits::{Abs, FloorLogBase, Pow};
+use malachite::num::conversion::string::options::ToSciOptions;
 use malachite::num::conversion::traits::{RoundingFrom, ToSci};
 use malachite::rounding_modes::RoundingMode;
-use malachite::{Integer, Rational};
+use malachite::{Integer, Natural, Rational};
 use std::cmp::Ordering;
 use std::fmt::{self, Display};
 use std::str::FromStr;
 
-#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum Format {
     Fraction,
     Mixed,
     #[default]
     Scientific,
+    // Rounded to a number of significant digits.
+    Digits(u64, RoundingMode),
+    // Rounded to a number of digits after the decimal point.
+    Decimals(u64, RoundingMode),
+    // Rounded to a number of significant digits, with an exponent that's a multiple of 3.
+    Engineering(u64, RoundingMode),
+    // Exact, in a base from 2 to 36, with the repeating part of the fraction in parentheses.
+    Base(u8),
+    // A continued fraction, e.g. [1; 2, 2, 2].
+    Continued,
+    // For pasting into documents, e.g. \frac{4}{3}.
+    Latex(Notation),
+    MathMl(Notation),
 }
 
+const MARKUPS: [(&str, Markup); 2] = [("latex", Markup::Latex), ("mathml", Markup::MathMl)];
+
+const NOTATIONS: [(&str, Notation); 3] = [
+    ("fraction", Notation::Fraction),
+    ("mixed", Notation::Mixed),
+    ("scientific", Notation::Scientific),
+];
+
+const ROUNDING_MODES: [(&str, RoundingMode); 5] = [
+    ("nearest", RoundingMode::Nearest),
+    ("down", RoundingMode::Down),
+    ("up", RoundingMode::Up),
+    ("floor", RoundingMode::Floor),
+    ("ceiling", RoundingMode::Ceiling),
+];
+
 impl Display for Format {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::Fraction => write!(f, "fraction"),
-            Self::Mixed => write!(f, "mixed"),
-            Self::Scientific => write!(f, "scientific"),
+        let (name, n, mode) = match *self {
+            Self::Fraction => return write!(f, "fraction"),
+            Self::Mixed => return write!(f, "mixed"),
+            Self::Scientific => return write!(f, "scientific"),
+            Self::Base(base) => return write!(f, "base {base}"),
+            Self::Continued => return write!(f, "continued"),
+            Self::Latex(notation) | Self::MathMl(notation) => {
+                let markup = if let Self::Latex(_) = self {
+                    "latex"
+                } else {
+                    "mathml"
+                };
+                return match NOTATIONS.iter().find(|&&(_, n)| n == notation) {
+                    Some(&(_, Notation::Fraction)) | None => write!(f, "{markup}"),
+                    Some((notation_name, _)) => write!(f, "{markup} {notation_name}"),
+                };
+            }
+            Self::Digits(n, mode) => ("digits", n, mode),
+            Self::Decimals(n, mode) => ("decimals", n, mode),
+            Self::Engineering(n, mode) => ("engineering", n, mode),
+        };
+        write!(f, "{name} {n}")?;
+        match ROUNDING_MODES.iter().find(|&&(_, m)| m == mode) {
+            Some(&(_, RoundingMode::Nearest)) | None => Ok(()),
+            Some((mode_name, _)) => write!(f, " {mode_name}"),
         }
     }
 }
 
 #[derive(Debug, thiserror::Error)]
-#[error("invalid format (it should be \"fraction\", \"mixed\" or \"scientific\")")]
+#[error(
+    "invalid format (it should be \"fraction\", \"mixed\", \"scientific\", \"continued\", \
+    \"base <2 to 36>\", \"latex\", \"mathml\", \"digits <n>\", \"decimals <n>\" or \"engineering <n>\", \
+    where latex and mathml can be followed by \"mixed\" or \"scientific\", and the last three by a \
+    rounding mode: \"nearest\", \"down\", \"up\", \"floor\" or \"ceiling\")"
+)]
 pub struct BadFormat;
 
 impl FromStr for Format {
     type Err = BadFormat;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.eq_ignore_ascii_case("fraction") {
-            Ok(Self::Fraction)
-        } else if s.eq_ignore_ascii_case("mixed") {
-            Ok(Self::Mixed)
-        } else if s.eq_ignore_ascii_case("scientific") {
-            Ok(Self::Scientific)
+        let words = s.split_whitespace().collect::<Vec<_>>();
+        if let Some(&(_, markup)) = words
+            .first()
+            .and_then(|name| MARKUPS.iter().find(|(m, _)| name.eq_ignore_ascii_case(m)))
+        {
+            let notation = match words[1..] {
+                [] => Notation::Fraction,
+                [notation] => {
+                    NOTATIONS
+                        .iter()
+                        .find(|(n, _)| notation.eq_ignore_ascii_case(n))
+                        .ok_or(BadFormat)?
+                        .1
+                }
+                _ => return Err(BadFormat),
+            };
+            return Ok(match markup {
+                Markup::Latex => Self::Latex(notation),
+                Markup::MathMl => Self::MathMl(notation),
+            });
+        }
+        let (name, n, mode) = match words[..] {
+            [name] if name.eq_ignore_ascii_case("fraction") => return Ok(Self::Fraction),
+            [name] if name.eq_ignore_ascii_case("mixed") => return Ok(Self::Mixed),
+            [name] if name.eq_ignore_ascii_case("scientific") => return Ok(Self::Scientific),
+            [name] if name.eq_ignore_ascii_case("continued") => return Ok(Self::Continued),
+            [name, base] if name.eq_ignore_ascii_case("base") => {
+                return match base.parse::<u8>() {
+                    Ok(base @ 2..=36) => Ok(Self::Base(base)),
+                    _ => Err(BadFormat),
+                };
+            }
+            [name, n] => (name, n, RoundingMode::Nearest),
+            [name, n, mode] => {
+                let Some(&(_, mode)) = ROUNDING_MODES
+                    .iter()
+                    .find(|(mode_name, _)| mode.eq_ignore_ascii_case(mode_name))
+                else {
+                    return Err(BadFormat);
+                };
+                (name, n, mode)
+            }
+            _ => return Err(BadFormat),
+        };
+        let n = n.parse::<u64>().map_err(|_| BadFormat)?;
+        if name.eq_ignore_ascii_case("decimals") {
+            Ok(Self::Decimals(n, mode))
+        } else if n == 0 {
+            Err(BadFormat)
+        } else if name.eq_ignore_ascii_case("digits") {
+            Ok(Self::Digits(n, mode))
+        } else if name.eq_ignore_ascii_case("engineering") {
+            Ok(Self::Engineering(n, mode))
         } else {
             Err(BadFormat)
         }
     }
 }
 
+// floor(log10(|n|)), for n != 0.
+fn exponent(n: &Rational) -> i64 {
+    n.abs().floor_log_base(&Rational::from(10))
+}
+
+// n rounded to a multiple of 10^e.
+fn round_to_power_of_10(n: &Rational, e: i64, mode: RoundingMode) -> Rational {
+    let unit = Rational::from(10).pow(e);
+    Rational::from(nt::round(&(n / &unit), mode)) * unit
+}
+
+// n rounded to a number of significant digits.
+fn round_to_digits(n: &Rational, digits: u64, mode: RoundingMode) -> Rational {
+    if *n == 0 {
+        return n.clone();
+    }
+    round_to_power_of_10(n, exponent(n) + 1 - digits as i64, mode)
+}
+
+// Writes n, which is a multiple of 10^-decimals, with exactly that many digits after the decimal point.
+pub fn fixed(n: &Rational, decimals: u64) -> String {
+    let scaled = Integer::try_from(n.abs() * Rational::from(10).pow(decimals)).unwrap();
+    let digits = format!("{scaled:0>width$}", width = decimals as usize + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - decimals as usize);
+    let sign = if *n < 0 { "-" } else { "" };
+    if fraction.is_empty() {
+        format!("{sign}{integer}")
+    } else {
+        format!("{sign}{integer}.{fraction}")
+    }
+}
+
+// Writes n, which is a multiple of 10^-decimals, with at most that many digits after the decimal point.
+fn trimmed(n: &Rational, decimals: u64) -> String {
+    let s = fixed(n, decimals);
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        s
+    }
+}
+
+// Writes n rounded to the format, marked with ≈ if it isn't exact.
+fn write_rounded(f: &mut fmt::Formatter<'_>, fmt: Format, n: &Rational) -> fmt::Result {
+    let (rounded, s) = match fmt {
+        Format::Digits(digits, mode) => {
+            let rounded = round_to_digits(n, digits, mode);
+            let mut options = ToSciOptions::default();
+            options.set_precision(digits);
+            let s = rounded.to_sci_with_options(options).to_string();
+            (rounded, s)
+        }
+        Format::Decimals(decimals, mode) => {
+            let rounded = round_to_power_of_10(n, -(decimals as i64), mode);
+            let s = fixed(&rounded, decimals);
+            (rounded, s)
+        }
+        Format::Engineering(digits, mode) => {
+            let rounded = round_to_digits(n, digits, mode);
+            let s = if rounded == 0 {
+                "0".to_string()
+            } else {
+                // Rounding may have added a digit, e.g. 999.9 to 1000.
+                let e = exponent(&rounded);
+                let e3 = e.div_euclid(3) * 3;
+                let mantissa = &rounded / Rational::from(10).pow(e3);
+                let decimals = (digits as i64 - 1 - (e - e3)).max(0) as u64;
+                match e3 {
+                    0 => trimmed(&mantissa, decimals),
+                    _ => format!("{}e{e3}", trimmed(&mantissa, decimals)),
+                }
+            };
+            (rounded, s)
+        }
+        _ => unreachable!(),
+    };
+    if rounded == *n {
+        write!(f, "{s}")
+    } else {
+        write!(f, "≈{s}")
+    }
+}
+
+// Numbers with larger denominators can have very long repeating parts,
+// so only the first MAX_BASE_FRACTION_DIGITS digits of their fraction are written, followed by "...".
+const MAX_EXACT_DENOMINATOR: u64 = 1 << 16;
+const MAX_BASE_FRACTION_DIGITS: usize = 64;
+
+fn write_digits<'a>(
+    f: &mut fmt::Formatter<'_>,
+    digits: impl IntoIterator<Item = &'a Natural>,
+) -> fmt::Result {
+    for digit in digits {
+        let digit = char::from_digit(u32::try_from(digit).unwrap(), 36).unwrap();
+        write!(f, "{digit}")?;
+    }
+    Ok(())
+}
+
+// The digits before the point are least significant first.
+fn write_integer_digits(f: &mut fmt::Formatter<'_>, integer: &[Natural]) -> fmt::Result {
+    if integer.is_empty() {
+        write!(f, "0")
+    } else {
+        write_digits(f, integer.iter().rev())
+    }
+}
+
+// Writes n in the base, e.g. 1/3 in base 2 is 0.(01).
+// This is the same digit expansion that parse::number reads decimal numbers with.
+fn write_in_base(f: &mut fmt::Formatter<'_>, base: u8, n: &Rational) -> fmt::Result {
+    let base = Natural::from(base);
+    if *n < 0 {
+        write!(f, "-")?;
+    }
+    if *n.denominator_ref() <= MAX_EXACT_DENOMINATOR {
+        let (integer, fraction) = n.to_digits(&base);
+        write_integer_digits(f, &integer)?;
+        if fraction.is_empty() {
+            return Ok(());
+        }
+        let (non_repeating, repeating) = fraction.slices_ref();
+        write!(f, ".")?;
+        write_digits(f, non_repeating)?;
+        if !repeating.is_empty() {
+            write!(f, "(")?;
+            write_digits(f, repeating)?;
+            write!(f, ")")?;
+        }
+        Ok(())
+    } else {
+        let (integer, fraction) = n.abs().digits(&base);
+        write_integer_digits(f, &integer)?;
+        let fraction = fraction
+            .take(MAX_BASE_FRACTION_DIGITS + 1)
+            .collect::<Vec<_>>();
+        write!(f, ".")?;
+        if fraction.len() > MAX_BASE_FRACTION_DIGITS {
+            write_digits(f, &fraction[..MAX_BASE_FRACTION_DIGITS])?;
+            write!(f, "...")
+        } else {
+            write_digits(f, &fraction)
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct FormattedValue<'a>(pub Format, pub &'a Value);
 
@@ -53,7 +304,86 @@ impl Display for FormattedValue<'_> {
         use Format::*;
         use Value::*;
         match self {
+            &FormattedValue(Latex(notation), value) => {
+                write!(f, "{}", Markup::Latex.value(value, notation))
+            }
+            &FormattedValue(MathMl(notation), value) => {
+                write!(
+                    f,
+                    "{}",
+                    Markup::MathMl.document(&Markup::MathMl.value(value, notation))
+                )
+            }
             FormattedValue(_, Undefined(u)) => write!(f, "{u}"),
+            &FormattedValue(fmt, List(l)) => {
+                write!(f, "[")?;
+                for (i, element) in l.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    match element.value_if_found() {
+                        Some(Undefined(_)) => write!(f, "undefined")?,
+                        Some(value) => write!(f, "{}", FormattedValue(fmt, value))?,
+                        None => write!(f, "...")?,
+                    }
+                }
+                write!(f, "]")
+            }
+            &FormattedValue(fmt, Matrix(m)) => {
+                write!(f, "{{")?;
+                for i in 0..m.rows() {
+                    if i != 0 {
+                        write!(f, "; ")?;
+                    }
+                    for (j, n) in m.row(i).iter().enumerate() {
+                        if j != 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{}", FormattedValue(fmt, &Number(n.clone())))?;
+                    }
+                }
+                write!(f, "}}")
+            }
+            &FormattedValue(fmt, Poly(p)) => {
+                // From the highest degree down, e.g. X^2 - 3*X + 1/2.
+                let mut first = true;
+                for (degree, c) in p.coefficients().iter().enumerate().rev() {
+                    if *c == 0 {
+                        continue;
+                    }
+                    match (first, *c < 0) {
+                        (true, true) => write!(f, "-")?,
+                        (true, false) => {}
+                        (false, true) => write!(f, " - ")?,
+                        (false, false) => write!(f, " + ")?,
+                    }
+                    first = false;
+                    let c = c.abs();
+                    if c != 1 || degree == 0 {
+                        let c = FormattedValue(fmt, &Number(c)).to_string();
+                        match (degree, c.contains(' ')) {
+                            (0, _) => write!(f, "{c}")?,
+                            (_, true) => write!(f, "({c})*")?,
+                            (_, false) => write!(f, "{c}*")?,
+                        }
+                    }
+                    match degree {
+                        0 => {}
+                        1 => write!(f, "X")?,
+                        _ => write!(f, "X^{degree}")?,
+                    }
+                }
+                Ok(())
+            }
+            &FormattedValue(fmt, Quantity(q)) => {
+                let value = FormattedValue(fmt, &Number(q.value().clone())).to_string();
+                if value.contains(' ') {
+                    write!(f, "({value}) {}", q.units())
+                } else {
+                    write!(f, "{value} {}", q.units())
+                }
+            }
+            FormattedValue(_, Mod(r)) => write!(f, "{} (mod {})", r.value(), r.modulus()),
             FormattedValue(Fraction, Number(n)) => write!(f, "{n}"),
             FormattedValue(Mixed, Number(n)) => {
                 let trunc = Integer::rounding_from(n, RoundingMode::Down);
@@ -71,6 +401,125 @@ impl Display for FormattedValue<'_> {
                 write!(f, "{fract}")
             }
             FormattedValue(Scientific, Number(n)) => write!(f, "{}", n.to_sci()),
+            &FormattedValue(Base(base), Number(n)) => write_in_base(f, base, n),
+            FormattedValue(Continued, Number(n)) => {
+                let terms = nt::continued_fraction(n);
+                match &terms[..] {
+                    [integer] => write!(f, "{integer}"),
+                    [integer, rest @ ..] => {
+                        write!(f, "[{integer}; ")?;
+                        for (i, term) in rest.iter().enumerate() {
+                            if i != 0 {
+                                write!(f, ", ")?;
+                            }
+                            write!(f, "{term}")?;
+                        }
+                        write!(f, "]")
+                    }
+                    [] => unreachable!(),
+                }
+            }
+            &FormattedValue(fmt @ (Digits(..) | Decimals(..) | Engineering(..)), Number(n)) => {
+                write_rounded(f, fmt, n)
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn formatted(format: &str, numer: i64, denom: i64) -> String {
+        let n = Value::Number(Rational::from_signeds(numer, denom));
+        FormattedValue(format.parse().unwrap(), &n).to_string()
+    }
+
+    #[test]
+    fn from_str_test() {
+        assert_eq!("Fraction".parse::<Format>().unwrap(), Format::Fraction);
+        assert_eq!(
+            "digits 50".parse::<Format>().unwrap(),
+            Format::Digits(50, RoundingMode::Nearest)
+        );
+        assert_eq!(
+            " decimals  2 FLOOR ".parse::<Format>().unwrap(),
+            Format::Decimals(2, RoundingMode::Floor)
+        );
+        for format in [
+            "fraction",
+            "base 2",
+            "continued",
+            "latex",
+            "mathml scientific",
+            "digits 50",
+            "decimals 0 floor",
+            "engineering 3 up",
+        ] {
+            assert_eq!(format.parse::<Format>().unwrap().to_string(), format);
+        }
+        assert_eq!("base 16".parse::<Format>().unwrap(), Format::Base(16));
+        assert_eq!(
+            "LaTeX mixed".parse::<Format>().unwrap(),
+            Format::Latex(Notation::Mixed)
+        );
+        for bad in [
+            "",
+            "digits",
+            "digits 0",
+            "digits x",
+            "decimals 2 exact",
+            "mixed 2",
+            "base 1",
+            "base 37",
+            "base 16 floor",
+            "latex 2",
+            "mathml continued",
+        ] {
+            assert!(bad.parse::<Format>().is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn rounded_test() {
+        assert_eq!(formatted("digits 5", 1, 3), "≈0.33333");
+        assert_eq!(formatted("digits 5", 1, 4), "0.25");
+        assert_eq!(formatted("digits 3", 99_999, 1), "≈1e5");
+        assert_eq!(formatted("digits 2 down", -299, 100), "≈-2.9");
+        assert_eq!(formatted("digits 2 floor", -299, 100), "≈-3");
+        assert_eq!(formatted("decimals 2", 5, 2), "2.50");
+        assert_eq!(formatted("decimals 2", 2, 3), "≈0.67");
+        assert_eq!(formatted("decimals 2 ceiling", -1, 300), "≈0.00");
+        assert_eq!(formatted("decimals 0", -7, 2), "≈-4");
+        assert_eq!(formatted("decimals 3", 123_456_789, 1), "123456789.000");
+        assert_eq!(formatted("engineering 3", 123_456, 1), "≈123e3");
+        assert_eq!(formatted("engineering 4", 1_234_567, 1), "≈1.235e6");
+        assert_eq!(formatted("engineering 3", 1, 2000), "500e-6");
+        assert_eq!(formatted("engineering 3", 9_999, 10), "≈1e3");
+        assert_eq!(formatted("engineering 3", 0, 1), "0");
+    }
+
+    #[test]
+    fn base_test() {
+        assert_eq!(formatted("base 16", 255, 1), "ff");
+        assert_eq!(formatted("base 2", 1, 3), "0.(01)");
+        assert_eq!(formatted("base 2", -13, 4), "-11.01");
+        assert_eq!(formatted("base 10", 1, 6), "0.1(6)");
+        assert_eq!(formatted("base 36", 35, 1), "z");
+        assert_eq!(formatted("base 3", 0, 1), "0");
+        let long = formatted("base 10", 1, 1_000_003);
+        assert!(long.starts_with("0.00000099999"), "{long}");
+        assert!(long.ends_with("..."));
+        assert_eq!(
+            formatted("base 2", 1, 1 << 40),
+            format!("0.{}1", "0".repeat(39))
+        );
+    }
+
+    #[test]
+    fn continued_test() {
+        assert_eq!(formatted("continued", 17, 12), "[1; 2, 2, 2]");
+        assert_eq!(formatted("continued", -7, 2), "[-4; 2]");
+        assert_eq!(formatted("continued", 5, 1), "5");
+    }
+}
@@ -0,0 +1,86 @@
+use crate::expression::Expression;
+use crate::math::value::Undefined;
+use crate::math::Value;
+use crate::program::{LazyExpression, RcConstant};
+use malachite::Natural;
+use std::rc::Rc;
+
+// The elements of a list are lazy, so a list can contain undefined values
+// (or even infinite loops) without being undefined itself.
+#[derive(Clone, Debug)]
+pub struct List {
+    elements: Rc<[RcConstant]>,
+}
+
+impl List {
+    pub fn new(elements: Vec<Expression>) -> Self {
+        Self {
+            elements: elements
+                .into_iter()
+                .map(|element| Rc::new(LazyExpression::new(element)))
+                .collect(),
+        }
+    }
+
+    pub fn from_values(values: impl IntoIterator<Item = Value>) -> Self {
+        Self::new(values.into_iter().map(Expression::Value).collect())
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RcConstant> {
+        self.elements.iter()
+    }
+
+    pub fn get(&self, index: &Value) -> Result<&RcConstant, Undefined> {
+        let Value::Number(index) = index else {
+            return Err(Undefined::TypeMismatch);
+        };
+        let index = Natural::try_from(index).map_err(|_| Undefined::IndexOutOfRange)?;
+        let index = usize::try_from(&index).map_err(|_| Undefined::IndexOutOfRange)?;
+        self.elements.get(index).ok_or(Undefined::IndexOutOfRange)
+    }
+
+    pub fn map(&self, f: impl Fn(Box<Expression>) -> Expression) -> Self {
+        Self {
+            elements: self
+                .elements
+                .iter()
+                .map(|element| {
+                    let element = Expression::Argument(Rc::clone(element));
+                    Rc::new(LazyExpression::new(f(Box::new(element))))
+                })
+                .collect(),
+        }
+    }
+}
+
+fn element(value: &Value, i: usize) -> Box<Expression> {
+    Box::new(match value {
+        Value::List(list) => Expression::Argument(Rc::clone(&list.elements[i])),
+        _ => Expression::Value(value.clone()),
+    })
+}
+
+// Applies a binary operator to each pair of elements of two lists of the same length,
+// or to each element of a list and a single value.
+pub fn elementwise(
+    left: &Value,
+    right: &Value,
+    op: fn(Box<Expression>, Box<Expression>) -> Expression,
+) -> Value {
+    let len = match (left, right) {
+        (Value::List(l), Value::List(r)) if l.len() != r.len() => {
+            return Value::Undefined(Undefined::SizeMismatch)
+        }
+        (Value::List(list), _) | (_, Value::List(list)) => list.len(),
+        _ => panic!("elementwise operation without a list"),
+    };
+    Value::List(List {
+        elements: (0..len)
+            .map(|i| Rc::new(LazyExpression::new(op(element(left, i), element(right, i)))))
+            .collect(),
+    })
+}
@@ -0,0 +1,385 @@
+// LaTeX and MathML renderings of values, for pasting into documents.
+
+use crate::math::format::fixed;
+use crate::math::Value;
+use malachite::num::arithmetic::traits::{Abs, Pow};
+use malachite::num::conversion::traits::{IsInteger, RoundingFrom, ToSci};
+use malachite::num::logic::traits::SignificantBits;
+use malachite::rounding_modes::RoundingMode;
+use malachite::{Integer, Rational};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Markup {
+    Latex,
+    MathMl,
+}
+
+// How numbers are written, like the plain text formats with the same names.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Notation {
+    #[default]
+    Fraction,
+    Mixed,
+    Scientific,
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// The exact decimal representation of n, if it has one, e.g. 9.81.
+fn finite_decimal(n: &Rational) -> Option<String> {
+    let max_decimals = n.denominator_ref().significant_bits();
+    (0..=max_decimals)
+        .find(|&decimals| (n * Rational::from(10).pow(decimals)).is_integer())
+        .map(|decimals| fixed(n, decimals))
+}
+
+impl Markup {
+    // Wraps a rendering so it can be pasted on its own.
+    pub fn document(self, content: &str) -> String {
+        match self {
+            Markup::Latex => content.to_string(),
+            Markup::MathMl => format!("<math>{content}</math>"),
+        }
+    }
+
+    pub fn row(self, parts: &[String]) -> String {
+        match self {
+            Markup::Latex => parts.concat(),
+            Markup::MathMl => format!("<mrow>{}</mrow>", parts.concat()),
+        }
+    }
+
+    // A number, or the digits of a number.
+    pub fn digits(self, digits: &str) -> String {
+        match self {
+            Markup::Latex => digits.to_string(),
+            Markup::MathMl => format!("<mn>{digits}</mn>"),
+        }
+    }
+
+    // An operator, given as plain text and as a LaTeX command where they're different, e.g. * and \cdot.
+    pub fn operator(self, text: &str, latex: &str) -> String {
+        match self {
+            Markup::Latex => format!(" {latex} "),
+            Markup::MathMl => format!("<mo>{}</mo>", escape_xml(text)),
+        }
+    }
+
+    // A name written upright if it's longer than one letter, e.g. x or \mathrm{len}.
+    pub fn identifier(self, name: &str) -> String {
+        match self {
+            Markup::Latex if name.chars().count() == 1 => name.to_string(),
+            Markup::Latex => format!("\\mathrm{{{}}}", name.replace('_', "\\_")),
+            Markup::MathMl => format!("<mi>{}</mi>", escape_xml(name)),
+        }
+    }
+
+    fn unit(self, name: &str) -> String {
+        match self {
+            Markup::Latex => format!("\\mathrm{{{name}}}"),
+            Markup::MathMl => format!("<mi mathvariant=\"normal\">{name}</mi>"),
+        }
+    }
+
+    pub fn text(self, text: &str) -> String {
+        match self {
+            Markup::Latex => format!("\\text{{{text}}}"),
+            Markup::MathMl => format!("<mtext>{}</mtext>", escape_xml(text)),
+        }
+    }
+
+    pub fn fraction(self, numerator: &str, denominator: &str) -> String {
+        match self {
+            Markup::Latex => format!("\\frac{{{numerator}}}{{{denominator}}}"),
+            Markup::MathMl => format!("<mfrac>{numerator}{denominator}</mfrac>"),
+        }
+    }
+
+    pub fn power(self, base: &str, exponent: &str) -> String {
+        match self {
+            Markup::Latex => format!("{base}^{{{exponent}}}"),
+            Markup::MathMl => format!("<msup>{base}{exponent}</msup>"),
+        }
+    }
+
+    // Content between brackets that grow with it, e.g. ( and ).
+    pub fn enclosed(self, open: &str, content: &str, close: &str) -> String {
+        match self {
+            Markup::Latex => {
+                let escape = |bracket| match bracket {
+                    "{" => "\\{",
+                    "}" => "\\}",
+                    bracket => bracket,
+                };
+                format!("\\left{}{content}\\right{}", escape(open), escape(close))
+            }
+            Markup::MathMl => format!("<mrow><mo>{open}</mo>{content}<mo>{close}</mo></mrow>"),
+        }
+    }
+
+    // A function applied to arguments, e.g. f(x, y).
+    pub fn call(self, name: &str, args: &[String]) -> String {
+        let args = self.separated(args);
+        match self {
+            Markup::Latex => format!(
+                "{}{}",
+                self.identifier(name),
+                self.enclosed("(", &args, ")")
+            ),
+            Markup::MathMl => self.row(&[
+                self.identifier(name),
+                "<mo>&#x2061;</mo>".to_string(),
+                self.enclosed("(", &args, ")"),
+            ]),
+        }
+    }
+
+    // Elements separated by commas.
+    pub fn separated(self, elements: &[String]) -> String {
+        let separator = match self {
+            Markup::Latex => ", ".to_string(),
+            Markup::MathMl => "<mo>,</mo>".to_string(),
+        };
+        elements.join(&separator)
+    }
+
+    pub fn matrix(self, rows: &[Vec<String>]) -> String {
+        match self {
+            Markup::Latex => {
+                let rows = rows
+                    .iter()
+                    .map(|row| row.join(" & "))
+                    .collect::<Vec<_>>()
+                    .join(" \\\\ ");
+                format!("\\begin{{pmatrix}}{rows}\\end{{pmatrix}}")
+            }
+            Markup::MathMl => {
+                let rows = rows
+                    .iter()
+                    .map(|row| {
+                        let cells = row
+                            .iter()
+                            .map(|cell| format!("<mtd>{cell}</mtd>"))
+                            .collect::<String>();
+                        format!("<mtr>{cells}</mtr>")
+                    })
+                    .collect::<String>();
+                self.enclosed("(", &format!("<mtable>{rows}</mtable>"), ")")
+            }
+        }
+    }
+
+    pub fn negative(self, content: &str) -> String {
+        match self {
+            Markup::Latex => format!("-{content}"),
+            Markup::MathMl => self.row(&["<mo>-</mo>".to_string(), content.to_string()]),
+        }
+    }
+
+    // A number as it's written in source code: a decimal if possible, or else a fraction.
+    pub fn source_number(self, n: &Rational) -> String {
+        match finite_decimal(&n.abs()) {
+            Some(decimal) if *n < 0 => self.negative(&self.digits(&decimal)),
+            Some(decimal) => self.digits(&decimal),
+            None => self.number(n, Notation::Fraction),
+        }
+    }
+
+    pub fn number(self, n: &Rational, notation: Notation) -> String {
+        if *n < 0 {
+            return self.negative(&self.number(&n.abs(), notation));
+        }
+        let fraction = |n: &Rational| {
+            let (numerator, denominator) = n.to_numerator_and_denominator();
+            self.fraction(
+                &self.digits(&numerator.to_string()),
+                &self.digits(&denominator.to_string()),
+            )
+        };
+        match notation {
+            _ if n.is_integer() && notation != Notation::Scientific => self.digits(&n.to_string()),
+            Notation::Fraction => fraction(n),
+            Notation::Mixed => {
+                let integer = Integer::rounding_from(n, RoundingMode::Down);
+                if integer == 0 {
+                    return fraction(n);
+                }
+                let rest = n - Rational::from(&integer);
+                let (numerator, denominator) = rest.to_numerator_and_denominator();
+                match self {
+                    Markup::Latex => format!("{integer}\\tfrac{{{numerator}}}{{{denominator}}}"),
+                    Markup::MathMl => {
+                        self.row(&[self.digits(&integer.to_string()), fraction(&rest)])
+                    }
+                }
+            }
+            Notation::Scientific => {
+                let sci = n.to_sci().to_string();
+                match sci.split_once('e') {
+                    Some((mantissa, exponent)) => {
+                        let exponent = exponent.trim_start_matches('+');
+                        let power = self.power(&self.digits("10"), &self.digits(exponent));
+                        match self {
+                            Markup::Latex => format!("{mantissa}\\times{power}"),
+                            Markup::MathMl => self.row(&[
+                                self.digits(mantissa),
+                                "<mo>&#xD7;</mo>".to_string(),
+                                power,
+                            ]),
+                        }
+                    }
+                    None => self.digits(&sci),
+                }
+            }
+        }
+    }
+
+    pub fn value(self, value: &Value, notation: Notation) -> String {
+        match value {
+            Value::Number(n) => self.number(n, notation),
+            Value::List(l) => {
+                let elements = l
+                    .iter()
+                    .map(|element| match element.value_if_found() {
+                        Some(Value::Undefined(_)) => self.text("undefined"),
+                        Some(value) => self.value(value, notation),
+                        None => match self {
+                            Markup::Latex => "\\ldots".to_string(),
+                            Markup::MathMl => "<mo>&#x2026;</mo>".to_string(),
+                        },
+                    })
+                    .collect::<Vec<_>>();
+                self.enclosed("[", &self.separated(&elements), "]")
+            }
+            Value::Matrix(m) => {
+                let rows = (0..m.rows())
+                    .map(|i| m.row(i).iter().map(|n| self.number(n, notation)).collect())
+                    .collect::<Vec<_>>();
+                self.matrix(&rows)
+            }
+            Value::Mod(r) => {
+                let value = self.digits(&r.value().to_string());
+                let modulus = self.digits(&r.modulus().to_string());
+                match self {
+                    Markup::Latex => format!("{value} \\pmod{{{modulus}}}"),
+                    Markup::MathMl => self.row(&[
+                        value,
+                        "<mspace width=\"1em\"/>".to_string(),
+                        self.enclosed("(", &format!("<mi>mod</mi>{modulus}"), ")"),
+                    ]),
+                }
+            }
+            Value::Poly(p) => {
+                // From the highest degree down, e.g. X^2 - 3X + 1/2.
+                let mut terms = vec![];
+                for (degree, c) in p.coefficients().iter().enumerate().rev() {
+                    if *c == 0 {
+                        continue;
+                    }
+                    let sign = if *c < 0 { "-" } else { "+" };
+                    if !terms.is_empty() || *c < 0 {
+                        terms.push(self.operator(sign, sign));
+                        if terms.len() == 1 {
+                            terms[0] = terms[0].trim().to_string();
+                        }
+                    }
+                    let c = c.abs();
+                    let x = match degree {
+                        0 => String::new(),
+                        1 => self.identifier("X"),
+                        _ => self.power(&self.identifier("X"), &self.digits(&degree.to_string())),
+                    };
+                    let coefficient = match c {
+                        _ if c == 1 && degree != 0 => String::new(),
+                        _ if degree == 0 || c.is_integer() || notation == Notation::Fraction => {
+                            self.number(&c, notation)
+                        }
+                        _ => self.enclosed("(", &self.number(&c, notation), ")"),
+                    };
+                    terms.push(coefficient + &x);
+                }
+                self.row(&terms)
+            }
+            Value::Quantity(q) => {
+                let units = q
+                    .unit_powers()
+                    .map(|(unit, exponent)| match exponent {
+                        1 => self.unit(unit),
+                        _ => self.power(&self.unit(unit), &self.digits(&exponent.to_string())),
+                    })
+                    .collect::<Vec<_>>();
+                let separator = match self {
+                    Markup::Latex => "\\,",
+                    Markup::MathMl => "<mo>&#x2062;</mo>",
+                };
+                let mut parts = vec![self.number(q.value(), notation)];
+                parts.extend(units);
+                self.row(&[parts.join(separator)])
+            }
+            Value::Undefined(u) => self.text(&u.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::math::Matrix;
+
+    fn frac(numer: i64, denom: i64) -> Rational {
+        Rational::from_signeds(numer, denom)
+    }
+
+    #[test]
+    fn latex_test() {
+        let latex = |n, notation| Markup::Latex.number(&n, notation);
+        assert_eq!(latex(frac(4, 3), Notation::Fraction), "\\frac{4}{3}");
+        assert_eq!(latex(frac(-4, 3), Notation::Fraction), "-\\frac{4}{3}");
+        assert_eq!(latex(frac(4, 3), Notation::Mixed), "1\\tfrac{1}{3}");
+        assert_eq!(latex(frac(1, 3), Notation::Mixed), "\\frac{1}{3}");
+        assert_eq!(latex(frac(133_000, 1), Notation::Scientific), "133000");
+        assert_eq!(
+            latex(
+                Rational::from(10).pow(20u64) * frac(4, 3),
+                Notation::Scientific
+            ),
+            "1.333333333333333\\times10^{20}"
+        );
+        assert_eq!(latex(frac(5, 1), Notation::Fraction), "5");
+        assert_eq!(
+            latex(Rational::from(10).pow(20u64), Notation::Scientific),
+            "1\\times10^{20}"
+        );
+        let matrix = Value::Matrix(Matrix::new(
+            2,
+            2,
+            vec![frac(1, 2), frac(2, 1), frac(0, 1), frac(-1, 1)],
+        ));
+        assert_eq!(
+            Markup::Latex.value(&matrix, Notation::Fraction),
+            "\\begin{pmatrix}\\frac{1}{2} & 2 \\\\ 0 & -1\\end{pmatrix}"
+        );
+        assert_eq!(Markup::Latex.source_number(&frac(981, 100)), "9.81");
+    }
+
+    #[test]
+    fn mathml_test() {
+        assert_eq!(
+            Markup::MathMl.number(&frac(-4, 3), Notation::Fraction),
+            "<mrow><mo>-</mo><mfrac><mn>4</mn><mn>3</mn></mfrac></mrow>"
+        );
+        assert_eq!(
+            Markup::MathMl.number(&frac(4, 3), Notation::Mixed),
+            "<mrow><mn>1</mn><mfrac><mn>1</mn><mn>3</mn></mfrac></mrow>"
+        );
+        assert_eq!(
+            Markup::MathMl.value(&Value::Number(frac(1, 2)), Notation::Fraction),
+            "<mfrac><mn>1</mn><mn>2</mn></mfrac>"
+        );
+        assert_eq!(Markup::MathMl.identifier("a<b"), "<mi>a&lt;b</mi>");
+    }
+}
@@ -0,0 +1,308 @@
+use crate::math::value::Undefined;
+use malachite::num::arithmetic::traits::{Parity, Reciprocal, UnsignedAbs};
+use malachite::num::basic::traits::{One, Zero};
+use malachite::num::logic::traits::SignificantBits;
+use malachite::{Integer, Rational};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Matrix {
+    rows: usize,
+    cols: usize,
+    entries: Vec<Rational>, // Row by row.
+}
+
+impl Matrix {
+    pub fn new(rows: usize, cols: usize, entries: Vec<Rational>) -> Self {
+        assert_eq!(rows * cols, entries.len());
+        Self {
+            rows,
+            cols,
+            entries,
+        }
+    }
+
+    pub fn identity(n: usize) -> Self {
+        let mut entries = vec![Rational::ZERO; n * n];
+        for i in 0..n {
+            entries[i * n + i] = Rational::ONE;
+        }
+        Self::new(n, n, entries)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn row(&self, i: usize) -> &[Rational] {
+        &self.entries[i * self.cols..(i + 1) * self.cols]
+    }
+
+    fn is_square(&self) -> bool {
+        self.rows == self.cols
+    }
+
+    pub fn map(mut self, f: impl Fn(Rational) -> Rational) -> Self {
+        self.entries = self.entries.into_iter().map(f).collect();
+        self
+    }
+
+    pub fn zip_with(
+        mut self,
+        other: &Self,
+        f: impl Fn(Rational, &Rational) -> Rational,
+    ) -> Result<Self, Undefined> {
+        if (self.rows, self.cols) != (other.rows, other.cols) {
+            return Err(Undefined::SizeMismatch);
+        }
+        self.entries = self
+            .entries
+            .into_iter()
+            .zip(&other.entries)
+            .map(|(a, b)| f(a, b))
+            .collect();
+        Ok(self)
+    }
+
+    pub fn mul(&self, other: &Self) -> Result<Self, Undefined> {
+        if self.cols != other.rows {
+            return Err(Undefined::SizeMismatch);
+        }
+        let mut entries = Vec::with_capacity(self.rows * other.cols);
+        for i in 0..self.rows {
+            for j in 0..other.cols {
+                let mut sum = Rational::ZERO;
+                for k in 0..self.cols {
+                    sum += &self.entries[i * self.cols + k] * &other.entries[k * other.cols + j];
+                }
+                entries.push(sum);
+            }
+        }
+        Ok(Self::new(self.rows, other.cols, entries))
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut entries = Vec::with_capacity(self.entries.len());
+        for j in 0..self.cols {
+            for i in 0..self.rows {
+                entries.push(self.entries[i * self.cols + j].clone());
+            }
+        }
+        Self::new(self.cols, self.rows, entries)
+    }
+
+    pub fn determinant(&self) -> Result<Rational, Undefined> {
+        if !self.is_square() {
+            return Err(Undefined::SizeMismatch);
+        }
+        let n = self.rows;
+        let mut m = self.entries.clone();
+        let mut det = Rational::ONE;
+        for col in 0..n {
+            let Some(pivot) = (col..n).find(|&row| m[row * n + col] != 0) else {
+                return Ok(Rational::ZERO);
+            };
+            if pivot != col {
+                for j in 0..n {
+                    m.swap(pivot * n + j, col * n + j);
+                }
+                det = -det;
+            }
+            let pivot = m[col * n + col].clone();
+            for row in col + 1..n {
+                let factor = &m[row * n + col] / &pivot;
+                if factor == 0 {
+                    continue;
+                }
+                for j in col..n {
+                    let d = &factor * &m[col * n + j];
+                    m[row * n + j] -= d;
+                }
+            }
+            det *= pivot;
+        }
+        Ok(det)
+    }
+
+    // Solves self * x = b by Gauss-Jordan elimination.
+    pub fn solve(&self, b: &Self) -> Result<Self, Undefined> {
+        if !self.is_square() || self.rows != b.rows {
+            return Err(Undefined::SizeMismatch);
+        }
+        let n = self.rows;
+        let k = b.cols;
+        let mut a = self.entries.clone();
+        let mut x = b.entries.clone();
+        for col in 0..n {
+            let pivot = (col..n)
+                .find(|&row| a[row * n + col] != 0)
+                .ok_or(Undefined::Singular)?;
+            if pivot != col {
+                for j in 0..n {
+                    a.swap(pivot * n + j, col * n + j);
+                }
+                for j in 0..k {
+                    x.swap(pivot * k + j, col * k + j);
+                }
+            }
+            let inverse = (&a[col * n + col]).reciprocal();
+            for j in 0..n {
+                a[col * n + j] *= &inverse;
+            }
+            for j in 0..k {
+                x[col * k + j] *= &inverse;
+            }
+            for row in 0..n {
+                if row == col || a[row * n + col] == 0 {
+                    continue;
+                }
+                let factor = a[row * n + col].clone();
+                for j in 0..n {
+                    let d = &factor * &a[col * n + j];
+                    a[row * n + j] -= d;
+                }
+                for j in 0..k {
+                    let d = &factor * &x[col * k + j];
+                    x[row * k + j] -= d;
+                }
+            }
+        }
+        Ok(Self::new(n, k, x))
+    }
+
+    pub fn inverse(&self) -> Result<Self, Undefined> {
+        if !self.is_square() {
+            return Err(Undefined::SizeMismatch);
+        }
+        self.solve(&Self::identity(self.rows))
+    }
+
+    pub fn pow(&self, exponent: &Rational) -> Result<Self, Undefined> {
+        if !self.is_square() {
+            return Err(Undefined::SizeMismatch);
+        }
+        let exponent = Integer::try_from(exponent).map_err(|_| Undefined::TypeMismatch)?;
+        let mut base = if exponent < 0 {
+            self.inverse()?
+        } else {
+            self.clone()
+        };
+        let mut exponent = exponent.unsigned_abs();
+        // Each entry of the result is a sum of products of exponent entries, so it has about
+        // exponent times the bits of the largest entry and the number of terms.
+        let entry_bits = base
+            .entries
+            .iter()
+            .map(|x| x.significant_bits())
+            .max()
+            .unwrap_or(0)
+            + (self.rows as u64).significant_bits();
+        let bits = u64::try_from(&exponent)
+            .ok()
+            .and_then(|e| entry_bits.checked_mul(e))
+            .and_then(|bits| bits.checked_mul(base.entries.len() as u64));
+        if exponent > 1 && bits.is_none_or(|bits| bits > super::max_bits()) {
+            return Err(Undefined::TooLarge);
+        }
+        let mut result = Self::identity(self.rows);
+        while exponent != 0 {
+            if exponent.odd() {
+                result = result.mul(&base)?;
+            }
+            exponent >>= 1;
+            if exponent != 0 {
+                base = base.mul(&base)?;
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn matrix(rows: usize, cols: usize, entries: &[i64]) -> Matrix {
+        Matrix::new(
+            rows,
+            cols,
+            entries.iter().map(|&n| Rational::from(n)).collect(),
+        )
+    }
+
+    #[test]
+    fn determinant_test() {
+        assert_eq!(matrix(1, 1, &[5]).determinant().unwrap(), 5);
+        assert_eq!(matrix(2, 2, &[1, 2, 3, 4]).determinant().unwrap(), -2);
+        assert_eq!(matrix(2, 2, &[0, 1, 1, 0]).determinant().unwrap(), -1);
+        assert_eq!(
+            matrix(3, 3, &[1, 2, 3, 4, 5, 6, 7, 8, 9])
+                .determinant()
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            matrix(3, 3, &[2, -3, 1, 2, 0, -1, 1, 4, 5])
+                .determinant()
+                .unwrap(),
+            49
+        );
+        assert!(matrix(2, 3, &[1, 2, 3, 4, 5, 6]).determinant().is_err());
+    }
+
+    #[test]
+    fn inverse_test() {
+        let m = matrix(2, 2, &[1, 2, 3, 4]);
+        let inverse = m.inverse().unwrap();
+        assert_eq!(
+            inverse,
+            Matrix::new(
+                2,
+                2,
+                vec![
+                    Rational::from(-2),
+                    Rational::ONE,
+                    Rational::from_signeds(3, 2),
+                    Rational::from_signeds(-1, 2)
+                ]
+            )
+        );
+        assert_eq!(m.mul(&inverse).unwrap(), Matrix::identity(2));
+        assert!(matches!(
+            matrix(2, 2, &[1, 2, 2, 4]).inverse(),
+            Err(Undefined::Singular)
+        ));
+    }
+
+    #[test]
+    fn solve_test() {
+        let a = matrix(3, 3, &[2, 1, -1, -3, -1, 2, -2, 1, 2]);
+        let b = matrix(3, 1, &[8, -11, -3]);
+        assert_eq!(a.solve(&b).unwrap(), matrix(3, 1, &[2, 3, -1]));
+        assert!(matches!(
+            a.solve(&matrix(2, 1, &[1, 2])),
+            Err(Undefined::SizeMismatch)
+        ));
+    }
+
+    #[test]
+    fn pow_test() {
+        let m = matrix(2, 2, &[1, 1, 1, 0]);
+        assert_eq!(
+            m.pow(&Rational::from(10)).unwrap(),
+            matrix(2, 2, &[89, 55, 55, 34])
+        );
+        assert_eq!(m.pow(&Rational::ZERO).unwrap(), Matrix::identity(2));
+        assert_eq!(
+            m.pow(&Rational::from(-1)).unwrap(),
+            matrix(2, 2, &[0, 1, 1, -1])
+        );
+        assert_eq!(
+            m.pow(&Rational::from(1_000_000_000)),
+            Err(Undefined::TooLarge)
+        );
+    }
+}
@@ -0,0 +1,343 @@
+use crate::math::number_theory as nt;
+use crate::math::value::{Undefined, Value};
+use malachite::num::arithmetic::traits::{CeilingSqrt, Gcd, Mod, ModPow, Parity};
+use malachite::num::basic::traits::{One, Two, Zero};
+use malachite::{Integer, Natural, Rational};
+use std::collections::HashMap;
+
+// An integer modulo some positive modulus.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Residue {
+    value: Natural, // Always less than the modulus.
+    modulus: Natural,
+}
+
+impl Residue {
+    pub fn new(x: &Rational, modulus: &Rational) -> Result<Self, Undefined> {
+        let modulus = Natural::try_from(modulus).map_err(|_| Undefined::OutOfDomain)?;
+        if modulus == 0 {
+            return Err(Undefined::OutOfDomain);
+        }
+        Self::from_rational(x, modulus)
+    }
+
+    // A fraction a/b is a * b^-1 modulo the modulus.
+    fn from_rational(x: &Rational, modulus: Natural) -> Result<Self, Undefined> {
+        let (numer, denom) = x.to_numerator_and_denominator();
+        let numer = Integer::from_sign_and_abs(*x >= 0, numer).mod_op(Integer::from(&modulus));
+        let numer = Natural::try_from(numer).unwrap();
+        let denom = nt::mod_inverse(denom % &modulus, &modulus).ok_or(Undefined::NotInvertible)?;
+        Ok(Self {
+            value: numer * denom % &modulus,
+            modulus,
+        })
+    }
+
+    pub fn value(&self) -> &Natural {
+        &self.value
+    }
+
+    pub fn modulus(&self) -> &Natural {
+        &self.modulus
+    }
+
+    fn with_value(&self, value: Natural) -> Self {
+        Self {
+            value: value % &self.modulus,
+            modulus: self.modulus.clone(),
+        }
+    }
+
+    fn check_modulus(&self, other: &Self) -> Result<(), Undefined> {
+        if self.modulus == other.modulus {
+            Ok(())
+        } else {
+            Err(Undefined::ModulusMismatch)
+        }
+    }
+
+    pub fn add(&self, other: &Self) -> Result<Self, Undefined> {
+        self.check_modulus(other)?;
+        Ok(self.with_value(&self.value + &other.value))
+    }
+
+    pub fn sub(&self, other: &Self) -> Result<Self, Undefined> {
+        self.check_modulus(other)?;
+        Ok(self.with_value(&self.value + &self.modulus - &other.value))
+    }
+
+    pub fn mul(&self, other: &Self) -> Result<Self, Undefined> {
+        self.check_modulus(other)?;
+        Ok(self.with_value(&self.value * &other.value))
+    }
+
+    pub fn div(&self, other: &Self) -> Result<Self, Undefined> {
+        self.mul(&other.inverse()?)
+    }
+
+    pub fn neg(&self) -> Self {
+        self.with_value(&self.modulus - &self.value)
+    }
+
+    pub fn inverse(&self) -> Result<Self, Undefined> {
+        nt::mod_inverse(self.value.clone(), &self.modulus)
+            .map(|value| self.with_value(value))
+            .ok_or(Undefined::NotInvertible)
+    }
+
+    // Fractional exponents are only supported for prime moduli,
+    // where x^(1/n) is a residue r such that r^n = x, if there is one.
+    pub fn pow(&self, exponent: &Rational) -> Result<Self, Undefined> {
+        if self.modulus == 1 {
+            return Ok(self.clone());
+        }
+        let (numer, denom) = exponent.to_numerator_and_denominator();
+        let base = if denom == 1 {
+            self.clone()
+        } else {
+            self.root(&denom)?
+        };
+        let base = if *exponent < 0 { base.inverse()? } else { base };
+        Ok(self.with_value(base.value.mod_pow(numer, &self.modulus)))
+    }
+
+    fn root(&self, n: &Natural) -> Result<Self, Undefined> {
+        let p = &self.modulus;
+        if !nt::is_prime(&Integer::from(p)) {
+            return Err(Undefined::OutOfDomain);
+        }
+        if self.value == 0 {
+            return Ok(self.clone());
+        }
+        let root = prime_root(&self.value, n, p)?;
+        // If n is even, the negation of a root is also a root. Pick the smaller one.
+        let negated = p - &root;
+        Ok(self.with_value(if n.even() && negated < root {
+            negated
+        } else {
+            root
+        }))
+    }
+}
+
+// The largest number of candidates tried when looking for a non-residue,
+// and of baby steps taken when finding a discrete logarithm.
+const ROOT_STEPS: u64 = 1 << 16;
+
+// Returns an r such that r^l = a modulo the prime p, and a primitive l'th root of unity.
+// l must be a prime dividing p - 1, and a must be a nonzero l'th power residue.
+fn prime_degree_root(
+    a: &Natural,
+    l: &Natural,
+    p: &Natural,
+) -> Result<(Natural, Natural), Undefined> {
+    // p - 1 = l^s * t, where t isn't divisible by l.
+    let mut t = p - Natural::ONE;
+    let mut l_s = Natural::ONE;
+    while (&t % l) == 0 {
+        t /= l;
+        l_s *= l;
+    }
+    // Find a number that isn't an l'th power residue.
+    // At least half of all numbers aren't, so there's usually one among the first few.
+    let order = p - Natural::ONE;
+    let mut non_residue = Natural::TWO;
+    while (&non_residue).mod_pow(&order / l, p) == 1 {
+        non_residue += Natural::ONE;
+        if non_residue > ROOT_STEPS {
+            return Err(Undefined::TooLarge);
+        }
+    }
+    // z generates the subgroup of order l^s, and zeta is a primitive l'th root of unity.
+    let z = non_residue.mod_pow(&t, p);
+    let zeta = (&z).mod_pow(&l_s / l, p);
+    // x^l = a * error, where error is in the subgroup generated by z.
+    let u = nt::mod_inverse(l % &t, &t).unwrap_or(Natural::ZERO);
+    let x = a.mod_pow(&u, p);
+    let a_inverse = nt::mod_inverse(a.clone(), p).unwrap();
+    let error = (&x).mod_pow(l, p) * a_inverse % p;
+    // Find k such that error = z^k, digit by digit in base l.
+    let mut k = Natural::ZERO;
+    let mut l_i = Natural::ONE;
+    let mut digit_log: Option<DiscreteLog> = None;
+    while l_i < l_s {
+        let z_minus_k = (&z).mod_pow(&l_s - &k, p);
+        let e = (&error * z_minus_k % p).mod_pow(&l_s / &l_i / l, p);
+        let j = match &digit_log {
+            _ if e == 1 => Natural::ZERO,
+            Some(digit_log) => digit_log.log(e),
+            None => digit_log.insert(DiscreteLog::new(&zeta, l, p)?).log(e),
+        };
+        k += j * &l_i;
+        l_i *= l;
+    }
+    // k is divisible by l, so (x * z^(-k/l))^l = a.
+    let correction = (&z).mod_pow(&l_s - k / l, p);
+    Ok((x * correction % p, zeta))
+}
+
+// Finds logarithms base zeta modulo p, where zeta has prime order l, with baby-step giant-step.
+struct DiscreteLog<'a> {
+    baby_steps: HashMap<Natural, Natural>, // zeta^i -> i, for i < m.
+    giant_step: Natural,                   // zeta^(-m).
+    m: Natural,
+    p: &'a Natural,
+}
+
+impl<'a> DiscreteLog<'a> {
+    fn new(zeta: &Natural, l: &Natural, p: &'a Natural) -> Result<Self, Undefined> {
+        let m = l.ceiling_sqrt();
+        if m > ROOT_STEPS {
+            return Err(Undefined::TooLarge);
+        }
+        let mut baby_steps = HashMap::new();
+        let mut zeta_i = Natural::ONE;
+        let mut i = Natural::ZERO;
+        while i < m {
+            baby_steps.entry(zeta_i.clone()).or_insert(i.clone());
+            zeta_i = zeta_i * zeta % p;
+            i += Natural::ONE;
+        }
+        // zeta_i = zeta^m now.
+        let giant_step = nt::mod_inverse(zeta_i, p).unwrap();
+        Ok(Self {
+            baby_steps,
+            giant_step,
+            m,
+            p,
+        })
+    }
+
+    // e must be a power of zeta.
+    fn log(&self, mut e: Natural) -> Natural {
+        let mut giant = Natural::ZERO;
+        loop {
+            if let Some(i) = self.baby_steps.get(&e) {
+                return giant * &self.m + i;
+            }
+            e = e * &self.giant_step % self.p;
+            giant += Natural::ONE;
+        }
+    }
+}
+
+// Returns an r such that r^n = a modulo the prime p, if there is one. a must be nonzero.
+fn prime_root(a: &Natural, n: &Natural, p: &Natural) -> Result<Natural, Undefined> {
+    let order = p - Natural::ONE;
+    let g = n.gcd(&order);
+    if a.mod_pow(&order / &g, p) != 1 {
+        return Err(Undefined::NoModularRoot);
+    }
+    // If x^g = a^u, where u * n/g = 1 modulo (p - 1)/g, then x^n = a.
+    let reduced_order = &order / &g;
+    let u = nt::mod_inverse(n / &g % &reduced_order, &reduced_order).unwrap_or(Natural::ZERO);
+    let mut root = a.mod_pow(u, p);
+    // Take g'th roots one prime factor at a time, making sure the root still has the remaining roots.
+    let mut remaining = g;
+    for l in nt::factor(&Integer::from(&remaining))? {
+        remaining /= &l;
+        let (mut x, zeta) = prime_degree_root(&root, &l, p)?;
+        while (&x).mod_pow(&order / &remaining, p) != 1 {
+            x = x * &zeta % p;
+        }
+        root = x;
+    }
+    Ok(root)
+}
+
+// Applies an operation to two residues, or a residue and a number.
+pub fn binary_op(
+    left: &Value,
+    right: &Value,
+    op: fn(&Residue, &Residue) -> Result<Residue, Undefined>,
+) -> Value {
+    let result = match (left, right) {
+        (Value::Mod(a), Value::Mod(b)) => op(a, b),
+        (Value::Mod(a), Value::Number(n)) => {
+            Residue::from_rational(n, a.modulus.clone()).and_then(|b| op(a, &b))
+        }
+        (Value::Number(n), Value::Mod(b)) => {
+            Residue::from_rational(n, b.modulus.clone()).and_then(|a| op(&a, b))
+        }
+        _ => panic!("modular operation without a residue"),
+    };
+    result.into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn residue(x: i64, m: i64) -> Residue {
+        Residue::new(&Rational::from(x), &Rational::from(m)).unwrap()
+    }
+
+    #[test]
+    fn arithmetic_test() {
+        let a = residue(-3, 7);
+        assert_eq!(a.value, 4);
+        assert_eq!(a.add(&residue(5, 7)).unwrap(), residue(2, 7));
+        assert_eq!(a.sub(&residue(5, 7)).unwrap(), residue(6, 7));
+        assert_eq!(a.mul(&residue(5, 7)).unwrap(), residue(6, 7));
+        assert_eq!(a.div(&residue(5, 7)).unwrap(), residue(5, 7));
+        assert_eq!(
+            Residue::new(&Rational::from_signeds(1, 2), &Rational::from(7)).unwrap(),
+            residue(4, 7)
+        );
+        assert_eq!(residue(2, 8).inverse(), Err(Undefined::NotInvertible));
+        assert_eq!(a.add(&residue(1, 8)), Err(Undefined::ModulusMismatch));
+    }
+
+    #[test]
+    fn pow_test() {
+        assert_eq!(
+            residue(4, 497).pow(&Rational::from(13)).unwrap(),
+            residue(445, 497)
+        );
+        assert_eq!(
+            residue(3, 10).pow(&Rational::from(-1)).unwrap(),
+            residue(7, 10)
+        );
+        assert_eq!(
+            residue(2, 10).pow(&Rational::from(-1)),
+            Err(Undefined::NotInvertible)
+        );
+    }
+
+    #[test]
+    fn root_test() {
+        let half = Rational::from_signeds(1, 2);
+        assert_eq!(residue(2, 7).pow(&half).unwrap(), residue(3, 7));
+        assert_eq!(residue(3, 7).pow(&half), Err(Undefined::NoModularRoot));
+        assert_eq!(residue(4, 8).pow(&half), Err(Undefined::OutOfDomain));
+        // Check every residue and root degree modulo some primes,
+        // including ones where p - 1 is divisible by high powers of the degree.
+        for p in [2, 3, 5, 13, 17, 97, 101, 257, 1009] {
+            for n in 1..=16 {
+                let n_th_power = |x: &Residue| x.pow(&Rational::from(n)).unwrap();
+                let powers = (0..p)
+                    .map(|r| n_th_power(&residue(r, p)))
+                    .collect::<Vec<_>>();
+                for x in 0..p {
+                    let x = residue(x, p);
+                    match x.pow(&Rational::from_signeds(1, n)) {
+                        Ok(root) => assert_eq!(n_th_power(&root), x),
+                        Err(_) => assert!(!powers.contains(&x), "{x:?}^(1/{n}) should exist"),
+                    }
+                }
+            }
+        }
+    }
+    #[test]
+    fn large_root_test() {
+        // p - 1 is divisible by l^2, so the root needs discrete logarithms base a root of unity.
+        let p: Rational = "24000000336000001177".parse().unwrap();
+        let l = Rational::from(1000000007);
+        let x = Residue::new(&Rational::from(5), &p)
+            .unwrap()
+            .pow(&l)
+            .unwrap();
+        let root = x.pow(&Rational::from_signeds(1, 1000000007)).unwrap();
+        assert_eq!(root.pow(&l).unwrap(), x);
+    }
+}
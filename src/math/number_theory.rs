@@ -0,0 +1,477 @@
+use crate::math::value::Undefined;
+use malachite::num::arithmetic::traits::{
+    Abs, BinomialCoefficient, CheckedSqrt, FloorSqrt, Gcd, JacobiSymbol, Lcm, Mod, ModInverse,
+    ModPow, Parity, Reciprocal,
+};
+use malachite::num::basic::traits::{One, Two, Zero};
+use malachite::num::conversion::traits::RoundingFrom;
+use malachite::num::logic::traits::{BitAccess, SignificantBits};
+use malachite::rounding_modes::RoundingMode;
+use malachite::{Integer, Natural, Rational};
+
+pub fn integer(n: &Rational) -> Result<Integer, Undefined> {
+    Integer::try_from(n).map_err(|_| Undefined::OutOfDomain)
+}
+
+pub fn round(n: &Rational, mode: RoundingMode) -> Integer {
+    Integer::rounding_from(n, mode)
+}
+
+// The remainder of x / y, with the same sign as y.
+pub fn modulo(x: &Rational, y: &Rational) -> Result<Rational, Undefined> {
+    if *y == 0 {
+        return Err(Undefined::OutOfDomain);
+    }
+    let quotient = Rational::from(round(&(x / y), RoundingMode::Floor));
+    Ok(x - y * quotient)
+}
+
+pub fn gcd(a: &Integer, b: &Integer) -> Natural {
+    a.unsigned_abs_ref().gcd(b.unsigned_abs_ref())
+}
+
+pub fn lcm(a: &Integer, b: &Integer) -> Natural {
+    a.unsigned_abs_ref().lcm(b.unsigned_abs_ref())
+}
+
+pub fn binomial(n: &Integer, k: &Integer) -> Integer {
+    if *k < 0 || (*n >= 0 && k > n) {
+        return Integer::ZERO;
+    }
+    Integer::binomial_coefficient(n.clone(), k.clone())
+}
+
+pub fn isqrt(x: &Rational) -> Result<Natural, Undefined> {
+    // floor(sqrt(x)) = floor(sqrt(floor(x))).
+    let x = Natural::try_from(round(x, RoundingMode::Floor)).map_err(|_| Undefined::OutOfDomain)?;
+    Ok(x.floor_sqrt())
+}
+
+// The fraction with the smallest denominator between a and b (inclusive), where a <= b.
+// If there are several, the one closest to 0.
+pub fn simplest_between(a: &Rational, b: &Rational) -> Rational {
+    debug_assert!(a <= b);
+    if *a <= 0 && *b >= 0 {
+        return Rational::ZERO;
+    }
+    if *b < 0 {
+        return -simplest_between(&-b, &-a);
+    }
+    let ceiling = Rational::from(round(a, RoundingMode::Ceiling));
+    if ceiling <= *b {
+        return ceiling;
+    }
+    // a and b are strictly between the same two integers, so the continued fraction goes on.
+    let floor = ceiling - Rational::ONE;
+    let rest = simplest_between(&(b - &floor).reciprocal(), &(a - &floor).reciprocal());
+    floor + rest.reciprocal()
+}
+
+// The continued fraction of x, [a0; a1, a2, ...], where a0 is an integer and the other terms are positive integers.
+// The last term is greater than 1, unless it's a0.
+pub fn continued_fraction(x: &Rational) -> Vec<Integer> {
+    let mut terms = vec![];
+    let mut x = x.clone();
+    loop {
+        let term = round(&x, RoundingMode::Floor);
+        let fraction = x - Rational::from(&term);
+        terms.push(term);
+        if fraction == 0 {
+            return terms;
+        }
+        x = fraction.reciprocal();
+    }
+}
+
+// The value of a continued fraction. All the terms but the first must be positive.
+pub fn from_continued_fraction(terms: &[Integer]) -> Rational {
+    let (last, rest) = terms.split_last().unwrap();
+    rest.iter().rev().fold(Rational::from(last), |x, term| {
+        Rational::from(term) + x.reciprocal()
+    })
+}
+
+// The convergents of x: the values of the prefixes of its continued fraction, ending with x itself.
+pub fn convergents(x: &Rational) -> Vec<Rational> {
+    let mut convergents = vec![];
+    let (mut h, mut previous_h) = (Integer::ONE, Integer::ZERO);
+    let (mut k, mut previous_k) = (Integer::ZERO, Integer::ONE);
+    for term in continued_fraction(x) {
+        (h, previous_h) = (&term * &h + &previous_h, h);
+        (k, previous_k) = (&term * &k + &previous_k, k);
+        convergents.push(Rational::from_integers_ref(&h, &k));
+    }
+    convergents
+}
+
+// The closest fraction to x with a denominator of at most max_denominator, which must be positive.
+// If there are two, the one with the smaller denominator.
+pub fn best_approximation(x: &Rational, max_denominator: &Natural) -> Rational {
+    let max_denominator = Integer::from(max_denominator);
+    let (mut h, mut previous_h) = (Integer::ONE, Integer::ZERO);
+    let (mut k, mut previous_k) = (Integer::ZERO, Integer::ONE);
+    for term in continued_fraction(x) {
+        let next_k = &term * &k + &previous_k;
+        if next_k > max_denominator {
+            // The best approximation is either the last convergent, or the closest semiconvergent to the next one.
+            let n = (&max_denominator - &previous_k) / &k;
+            let semiconvergent = Rational::from_integers(&n * &h + previous_h, n * &k + previous_k);
+            let convergent = Rational::from_integers(h, k);
+            return if (&semiconvergent - x).abs() < (&convergent - x).abs() {
+                semiconvergent
+            } else {
+                convergent
+            };
+        }
+        (h, previous_h) = (&term * &h + &previous_h, h);
+        (k, previous_k) = (next_k, k);
+    }
+    x.clone()
+}
+
+// b^e mod m. If e is negative, b must be invertible modulo m.
+pub fn modpow(b: &Integer, e: &Integer, m: &Integer) -> Result<Natural, Undefined> {
+    let m = Natural::try_from(m).map_err(|_| Undefined::OutOfDomain)?;
+    if m == 0 {
+        return Err(Undefined::OutOfDomain);
+    }
+    if m == 1 {
+        return Ok(Natural::ZERO);
+    }
+    let mut b = Natural::try_from(b.mod_op(Integer::from(&m))).unwrap();
+    if *e < 0 {
+        b = mod_inverse(b, &m).ok_or(Undefined::NotInvertible)?;
+    }
+    Ok(b.mod_pow(e.unsigned_abs_ref(), &m))
+}
+
+// The inverse of x modulo m, where x is already reduced modulo m.
+pub fn mod_inverse(x: Natural, m: &Natural) -> Option<Natural> {
+    if *m == 1 {
+        return Some(Natural::ZERO);
+    }
+    if x == 0 {
+        return None;
+    }
+    x.mod_inverse(m)
+}
+
+const SMALL_PRIMES: [u32; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+// The Miller-Rabin test. n must be odd and greater than the base.
+fn is_strong_probable_prime(n: &Natural, base: &Natural) -> bool {
+    let n_minus_1 = n - Natural::ONE;
+    let s = n_minus_1.trailing_zeros().unwrap();
+    let d = &n_minus_1 >> s;
+    let mut x = base.mod_pow(&d, n);
+    if x == 1 || x == n_minus_1 {
+        return true;
+    }
+    for _ in 1..s {
+        x = (&x * &x) % n;
+        if x == n_minus_1 {
+            return true;
+        }
+    }
+    false
+}
+
+// The strong Lucas test, with parameters chosen by Selfridge's method.
+// n must be odd, and not a perfect square.
+fn is_strong_lucas_probable_prime(n: &Natural) -> bool {
+    let n_int = Integer::from(n);
+    let mut d = Integer::from(5);
+    loop {
+        match (&d).jacobi_symbol(&n_int) {
+            -1 => break,
+            0 if d.unsigned_abs_ref() != n => return false,
+            _ => {}
+        }
+        d = if d > 0 {
+            -d - Integer::TWO
+        } else {
+            -d + Integer::TWO
+        };
+    }
+    let modulo = |x: Integer| x.mod_op(&n_int);
+    let half = |x: Integer| {
+        let x = if x.odd() { x + &n_int } else { x };
+        x >> 1
+    };
+    let q = modulo((Integer::ONE - &d) >> 2);
+    let d = modulo(d);
+    let n_plus_1 = n + Natural::ONE;
+    let s = n_plus_1.trailing_zeros().unwrap();
+    let k = &n_plus_1 >> s;
+    let mut u = Integer::ONE;
+    let mut v = Integer::ONE;
+    let mut q_k = q.clone();
+    for i in (0..k.significant_bits() - 1).rev() {
+        u = modulo(&u * &v);
+        v = modulo(&v * &v - Integer::TWO * &q_k);
+        q_k = modulo(&q_k * &q_k);
+        if k.get_bit(i) {
+            (u, v) = (half(&u + &v), half(&d * &u + &v));
+            u = modulo(u);
+            v = modulo(v);
+            q_k = modulo(&q_k * &q);
+        }
+    }
+    if u == 0 || v == 0 {
+        return true;
+    }
+    for _ in 1..s {
+        v = modulo(&v * &v - Integer::TWO * &q_k);
+        if v == 0 {
+            return true;
+        }
+        q_k = modulo(&q_k * &q_k);
+    }
+    false
+}
+
+// The Baillie-PSW primality test. It's exact for all n < 2^64,
+// and there are no known composite numbers that pass it.
+pub fn is_prime(n: &Integer) -> bool {
+    let Ok(n) = Natural::try_from(n) else {
+        return false;
+    };
+    if n < 2 {
+        return false;
+    }
+    for p in SMALL_PRIMES {
+        if n == p {
+            return true;
+        }
+        if (&n % Natural::from(p)) == 0 {
+            return false;
+        }
+    }
+    if n < 37 * 37 {
+        return true;
+    }
+    if !is_strong_probable_prime(&n, &Natural::TWO) {
+        return false;
+    }
+    if (&n).checked_sqrt().is_some() {
+        return false;
+    }
+    is_strong_lucas_probable_prime(&n)
+}
+
+// The maximum number of steps of Pollard's rho algorithm in a single factorization.
+// That's enough to find prime factors of up to about 32 bits.
+const RHO_STEPS: u64 = 1 << 18;
+// The number of steps between gcds in Pollard's rho algorithm.
+const RHO_BATCH: u64 = 128;
+
+// Finds a nontrivial factor of the odd composite number n using Pollard's rho algorithm,
+// with Brent's cycle detection and batched gcds.
+// Each step is taken from the budget, and if it runs out, the factor is too large to find.
+fn pollard_rho(n: &Natural, budget: &mut u64) -> Result<Natural, Undefined> {
+    let distance = |x: &Natural, y: &Natural| if x > y { x - y } else { y - x };
+    let mut c = Natural::ONE;
+    loop {
+        let f = |x: &Natural| (x * x + &c) % n;
+        let mut x;
+        let mut y = Natural::TWO;
+        let mut saved_y = y.clone();
+        let mut product = Natural::ONE;
+        let mut d = Natural::ONE;
+        let mut cycle_length = 1;
+        while d == 1 {
+            *budget = budget
+                .checked_sub(2 * cycle_length)
+                .ok_or(Undefined::TooLarge)?;
+            x = y.clone();
+            for _ in 0..cycle_length {
+                y = f(&y);
+            }
+            let mut steps = 0;
+            while steps < cycle_length && d == 1 {
+                saved_y = y.clone();
+                let batch = RHO_BATCH.min(cycle_length - steps);
+                for _ in 0..batch {
+                    y = f(&y);
+                    product = product * distance(&x, &y) % n;
+                }
+                d = (&product).gcd(n);
+                steps += batch;
+            }
+            if d == *n {
+                // The batch went past the factor, so go over it again one step at a time.
+                loop {
+                    saved_y = f(&saved_y);
+                    d = distance(&x, &saved_y).gcd(n);
+                    if d != 1 {
+                        break;
+                    }
+                }
+            }
+            cycle_length *= 2;
+        }
+        if d != *n {
+            return Ok(d);
+        }
+        c += Natural::ONE;
+    }
+}
+
+// Returns the prime factors of n in ascending order, with multiplicity.
+// It's undefined if n has more than one prime factor too large to find.
+pub fn factor(n: &Integer) -> Result<Vec<Natural>, Undefined> {
+    let mut n = Natural::try_from(n).map_err(|_| Undefined::OutOfDomain)?;
+    if n == 0 {
+        return Err(Undefined::OutOfDomain);
+    }
+    let mut factors = vec![];
+    for p in SMALL_PRIMES.map(Natural::from) {
+        while (&n % &p) == 0 {
+            n /= &p;
+            factors.push(p.clone());
+        }
+    }
+    let mut budget = RHO_STEPS;
+    let mut to_factor = vec![n];
+    while let Some(n) = to_factor.pop() {
+        if n == 1 {
+            continue;
+        }
+        if is_prime(&Integer::from(&n)) {
+            factors.push(n);
+            continue;
+        }
+        let d = pollard_rho(&n, &mut budget)?;
+        to_factor.push(&n / &d);
+        to_factor.push(d);
+    }
+    factors.sort();
+    Ok(factors)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn is_prime_test() {
+        let primes = (0..1000)
+            .filter(|&n| is_prime(&Integer::from(n)))
+            .collect::<Vec<_>>();
+        let expected = (0..1000)
+            .filter(|&n: &i32| n >= 2 && (2..n).all(|d| n % d != 0))
+            .collect::<Vec<_>>();
+        assert_eq!(primes, expected);
+        assert!(!is_prime(&Integer::from(-7)));
+        assert!(is_prime(&Integer::from(1_000_003)));
+        // Strong pseudoprimes to base 2.
+        assert!(!is_prime(&Integer::from(2047)));
+        assert!(!is_prime(&Integer::from(3_215_031_751u64)));
+        // A Carmichael number.
+        assert!(!is_prime(&Integer::from(561)));
+        // 2^89 - 1 is a Mersenne prime.
+        assert!(is_prime(&((Integer::ONE << 89) - Integer::ONE)));
+        assert!(!is_prime(&((Integer::ONE << 88) - Integer::ONE)));
+    }
+
+    #[test]
+    fn factor_test() {
+        let factors = |n: u64| {
+            factor(&Integer::from(n))
+                .unwrap()
+                .into_iter()
+                .map(|p| u64::try_from(&p).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(factors(1), Vec::<u64>::new());
+        assert_eq!(factors(360), [2, 2, 2, 3, 3, 5]);
+        assert_eq!(factors(1_000_003), [1_000_003]);
+        assert_eq!(factors(600_851_475_143), [71, 839, 1471, 6857]);
+        assert_eq!(factors(1_000_003 * 999_983), [999_983, 1_000_003]);
+        assert_eq!(
+            factors(4_294_967_291 * 4_294_967_279),
+            [4_294_967_279, 4_294_967_291]
+        );
+        assert!(factor(&Integer::ZERO).is_err());
+        // Both factors are far too large for Pollard's rho algorithm.
+        let semiprime =
+            ((Integer::ONE << 89) - Integer::ONE) * ((Integer::ONE << 107) - Integer::ONE);
+        assert_eq!(factor(&semiprime), Err(Undefined::TooLarge));
+    }
+
+    #[test]
+    fn modulo_test() {
+        let r = |n: i64, d: i64| Rational::from_signeds(n, d);
+        assert_eq!(modulo(&r(7, 1), &r(3, 1)).unwrap(), 1);
+        assert_eq!(modulo(&r(-7, 1), &r(3, 1)).unwrap(), 2);
+        assert_eq!(modulo(&r(7, 1), &r(-3, 1)).unwrap(), -2);
+        assert_eq!(modulo(&r(7, 2), &r(1, 1)).unwrap(), r(1, 2));
+        assert!(modulo(&r(7, 1), &r(0, 1)).is_err());
+    }
+
+    #[test]
+    fn simplest_between_test() {
+        let frac = Rational::from_signeds;
+        assert_eq!(simplest_between(&frac(-1, 2), &frac(3, 1)), 0);
+        assert_eq!(simplest_between(&frac(3, 2), &frac(5, 2)), 2);
+        assert_eq!(simplest_between(&frac(1, 3), &frac(1, 2)), frac(1, 2));
+        assert_eq!(simplest_between(&frac(3, 10), &frac(2, 5)), frac(1, 3));
+        assert_eq!(simplest_between(&frac(-2, 5), &frac(-3, 10)), frac(-1, 3));
+        assert_eq!(
+            simplest_between(&frac(314, 100), &frac(315, 100)),
+            frac(22, 7)
+        );
+        assert_eq!(
+            simplest_between(&frac(3141592, 1000000), &frac(3141593, 1000000)),
+            frac(355, 113)
+        );
+    }
+
+    #[test]
+    fn continued_fraction_test() {
+        let frac = Rational::from_signeds;
+        let terms = |x: &Rational| {
+            continued_fraction(x)
+                .into_iter()
+                .map(|t| i64::try_from(&t).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(terms(&frac(17, 12)), [1, 2, 2, 2]);
+        assert_eq!(terms(&frac(-7, 2)), [-4, 2]);
+        assert_eq!(terms(&frac(5, 1)), [5]);
+        assert_eq!(terms(&frac(1, 3)), [0, 3]);
+        for x in [frac(17, 12), frac(-7, 2), frac(5, 1), frac(355, 113)] {
+            assert_eq!(from_continued_fraction(&continued_fraction(&x)), x);
+        }
+        assert_eq!(
+            convergents(&frac(17, 12)),
+            [frac(1, 1), frac(3, 2), frac(7, 5), frac(17, 12)]
+        );
+    }
+
+    #[test]
+    fn best_approximation_test() {
+        let frac = Rational::from_signeds;
+        let pi = frac(3_141_592_653_589_793i64, 1_000_000_000_000_000);
+        let best = |x: &Rational, n: u64| best_approximation(x, &Natural::from(n));
+        assert_eq!(best(&pi, 1), 3);
+        assert_eq!(best(&pi, 7), frac(22, 7));
+        assert_eq!(best(&pi, 100), frac(311, 99));
+        assert_eq!(best(&pi, 1000), frac(355, 113));
+        assert_eq!(best(&-&pi, 10), frac(-22, 7));
+        assert_eq!(best(&frac(3, 8), 100), frac(3, 8));
+        // 1/2 and 1/3 are equally close to 5/12.
+        assert_eq!(best(&frac(5, 12), 3), frac(1, 2));
+        assert_eq!(best(&frac(5, 12), 4), frac(1, 2));
+    }
+
+    #[test]
+    fn modpow_test() {
+        let i = Integer::from;
+        assert_eq!(modpow(&i(4), &i(13), &i(497)).unwrap(), 445);
+        assert_eq!(modpow(&i(-2), &i(3), &i(7)).unwrap(), 6);
+        assert_eq!(modpow(&i(3), &i(-1), &i(10)).unwrap(), 7);
+        assert!(modpow(&i(4), &i(-1), &i(10)).is_err());
+        assert_eq!(modpow(&i(5), &i(0), &i(1)).unwrap(), 0);
+    }
+}
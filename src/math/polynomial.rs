@@ -0,0 +1,404 @@
+use crate::math::number_theory as nt;
+use crate::math::value::{Undefined, Value};
+use malachite::num::arithmetic::traits::{Lcm, Pow, Reciprocal, SaturatingSub};
+use malachite::num::basic::traits::{One, Zero};
+use malachite::num::conversion::traits::IsInteger;
+use malachite::num::logic::traits::SignificantBits;
+use malachite::{Integer, Natural, Rational};
+
+// A polynomial in the variable X, with exact rational coefficients.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polynomial {
+    coefficients: Vec<Rational>, // From the constant term up. The last one is never zero.
+}
+
+impl Polynomial {
+    pub fn new(mut coefficients: Vec<Rational>) -> Self {
+        while coefficients.last().is_some_and(|c| *c == 0) {
+            coefficients.pop();
+        }
+        Self { coefficients }
+    }
+
+    pub fn x() -> Self {
+        Self::new(vec![Rational::ZERO, Rational::ONE])
+    }
+
+    pub fn constant(c: Rational) -> Self {
+        Self::new(vec![c])
+    }
+
+    pub fn coefficients(&self) -> &[Rational] {
+        &self.coefficients
+    }
+
+    // The zero polynomial has no degree.
+    pub fn degree(&self) -> Option<usize> {
+        self.coefficients.len().checked_sub(1)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.coefficients.is_empty()
+    }
+
+    fn leading_coefficient(&self) -> &Rational {
+        self.coefficients.last().unwrap()
+    }
+
+    pub fn add(&self, other: &Self) -> Self {
+        let len = self.coefficients.len().max(other.coefficients.len());
+        let zero = Rational::ZERO;
+        Self::new(
+            (0..len)
+                .map(|i| {
+                    let a = self.coefficients.get(i).unwrap_or(&zero);
+                    let b = other.coefficients.get(i).unwrap_or(&zero);
+                    a + b
+                })
+                .collect(),
+        )
+    }
+
+    pub fn neg(&self) -> Self {
+        self.scale(&-Rational::ONE)
+    }
+
+    pub fn sub(&self, other: &Self) -> Self {
+        self.add(&other.neg())
+    }
+
+    pub fn scale(&self, c: &Rational) -> Self {
+        Self::new(self.coefficients.iter().map(|a| a * c).collect())
+    }
+
+    pub fn mul(&self, other: &Self) -> Self {
+        if self.is_zero() || other.is_zero() {
+            return Self::new(vec![]);
+        }
+        let mut coefficients =
+            vec![Rational::ZERO; self.coefficients.len() + other.coefficients.len() - 1];
+        for (i, a) in self.coefficients.iter().enumerate() {
+            for (j, b) in other.coefficients.iter().enumerate() {
+                coefficients[i + j] += a * b;
+            }
+        }
+        Self::new(coefficients)
+    }
+
+    pub fn div_rem(&self, divisor: &Self) -> Result<(Self, Self), Undefined> {
+        let Some(divisor_degree) = divisor.degree() else {
+            return Err(if self.is_zero() {
+                Undefined::ZeroOverZero
+            } else {
+                Undefined::Infinity
+            });
+        };
+        let leading_inverse = divisor.leading_coefficient().reciprocal();
+        let mut remainder = self.coefficients.clone();
+        let mut quotient = vec![Rational::ZERO; remainder.len().saturating_sub(divisor_degree)];
+        for i in (0..quotient.len()).rev() {
+            let factor = &remainder[i + divisor_degree] * &leading_inverse;
+            for (j, d) in divisor.coefficients.iter().enumerate() {
+                remainder[i + j] -= &factor * d;
+            }
+            quotient[i] = factor;
+        }
+        remainder.truncate(divisor_degree);
+        Ok((Self::new(quotient), Self::new(remainder)))
+    }
+
+    // Exact division. It's undefined if there's a remainder, because the result isn't a polynomial.
+    pub fn div(&self, divisor: &Self) -> Result<Self, Undefined> {
+        let (quotient, remainder) = self.div_rem(divisor)?;
+        if remainder.is_zero() {
+            Ok(quotient)
+        } else {
+            Err(Undefined::NotPolynomial)
+        }
+    }
+
+    // The greatest common divisor, normalized to have a leading coefficient of 1.
+    pub fn gcd(&self, other: &Self) -> Self {
+        let mut a = self.clone();
+        let mut b = other.clone();
+        while !b.is_zero() {
+            let (_, remainder) = a.div_rem(&b).unwrap();
+            a = b;
+            b = remainder;
+        }
+        if a.is_zero() {
+            return a;
+        }
+        let leading_inverse = a.leading_coefficient().reciprocal();
+        a.scale(&leading_inverse)
+    }
+
+    pub fn derivative(&self) -> Self {
+        Self::new(
+            self.coefficients
+                .iter()
+                .enumerate()
+                .skip(1)
+                .map(|(i, c)| c * Rational::from(i))
+                .collect(),
+        )
+    }
+
+    pub fn eval(&self, x: &Rational) -> Rational {
+        // Horner's method.
+        let mut result = Rational::ZERO;
+        for c in self.coefficients.iter().rev() {
+            result *= x;
+            result += c;
+        }
+        result
+    }
+
+    pub fn pow(&self, exponent: &Rational) -> Result<Self, Undefined> {
+        let exponent = u64::try_from(exponent).map_err(|_| {
+            if *exponent < 0 || !exponent.is_integer() {
+                Undefined::NotPolynomial
+            } else {
+                Undefined::TooLarge
+            }
+        })?;
+        // Written over a common denominator d, the coefficients of the result are integers
+        // of at most the exponent'th power of the sum of the coefficients' absolute values,
+        // over d to the exponent.
+        let denominator = self
+            .coefficients
+            .iter()
+            .fold(Natural::ONE, |lcm, c| lcm.lcm(c.denominator_ref()));
+        let norm = self
+            .coefficients
+            .iter()
+            .map(|c| c.numerator_ref() * (&denominator / c.denominator_ref()))
+            .sum::<Natural>();
+        let log2 = |n: &Natural| n.saturating_sub(&Natural::ONE).significant_bits();
+        let coefficient_bits = (log2(&norm) + log2(&denominator))
+            .saturating_mul(exponent)
+            .saturating_add(1);
+        let degree = self.degree().unwrap_or(0) as u64;
+        let bits = degree
+            .saturating_mul(exponent)
+            .saturating_add(1)
+            .saturating_mul(coefficient_bits);
+        if bits > super::max_bits() {
+            return Err(Undefined::TooLarge);
+        }
+        let mut result = Self::constant(Rational::ONE);
+        let mut base = self.clone();
+        let mut exponent = exponent;
+        while exponent != 0 {
+            if exponent & 1 == 1 {
+                result = result.mul(&base);
+            }
+            exponent >>= 1;
+            if exponent != 0 {
+                base = base.mul(&base);
+            }
+        }
+        Ok(result)
+    }
+
+    // Returns the distinct rational roots, in ascending order.
+    pub fn rational_roots(&self) -> Result<Vec<Rational>, Undefined> {
+        if self.is_zero() {
+            return Err(Undefined::OutOfDomain);
+        }
+        let mut roots = vec![];
+        // Divide by the highest power of X that divides the polynomial.
+        let zeros = self.coefficients.iter().take_while(|c| **c == 0).count();
+        if zeros > 0 {
+            roots.push(Rational::ZERO);
+        }
+        let mut reduced = Self::new(self.coefficients[zeros..].to_vec());
+        if reduced.degree() == Some(1) {
+            roots.push(reduced.linear_root());
+            roots.sort();
+            return Ok(roots);
+        }
+        // By the rational root theorem, every root is ±p/q,
+        // where p divides the constant term and q divides the leading coefficient,
+        // after multiplying by the denominators to make the coefficients integers.
+        let denominators = reduced
+            .coefficients
+            .iter()
+            .fold(Natural::ONE, |lcm, c| lcm.lcm(c.denominator_ref()));
+        let scaled = reduced.scale(&Rational::from(denominators));
+        let constant = Integer::try_from(&scaled.coefficients[0]).unwrap();
+        let leading = Integer::try_from(scaled.leading_coefficient()).unwrap();
+        let numerators = divisors(&constant)?;
+        let denominators = divisors(&leading)?;
+        if numerators.len() * denominators.len() > MAX_ROOT_CANDIDATES {
+            return Err(Undefined::TooLarge);
+        }
+        'search: for p in &numerators {
+            for q in &denominators {
+                for sign in [false, true] {
+                    let candidate = Rational::from_sign_and_naturals(sign, p.clone(), q.clone());
+                    if reduced.eval(&candidate) != 0 {
+                        continue;
+                    }
+                    // Divide out the root, so once the rest is linear its root is known directly.
+                    let factor = Self::new(vec![-&candidate, Rational::ONE]);
+                    while reduced.eval(&candidate) == 0 {
+                        reduced = reduced.div(&factor)?;
+                    }
+                    roots.push(candidate);
+                    match reduced.degree() {
+                        Some(1) => {
+                            roots.push(reduced.linear_root());
+                            break 'search;
+                        }
+                        Some(0) => break 'search,
+                        _ => {}
+                    }
+                }
+            }
+        }
+        roots.sort();
+        Ok(roots)
+    }
+
+    // The root of a polynomial of degree 1.
+    fn linear_root(&self) -> Rational {
+        -&self.coefficients[0] / &self.coefficients[1]
+    }
+}
+
+// The maximum number of candidates for rational roots that are tried.
+const MAX_ROOT_CANDIDATES: usize = 1 << 16;
+
+// The positive divisors of a nonzero integer.
+// It's undefined if there are too many of them to try as candidates for rational roots.
+fn divisors(n: &Integer) -> Result<Vec<Natural>, Undefined> {
+    let mut divisors = vec![Natural::ONE];
+    let factors = nt::factor(&Integer::from(n.unsigned_abs_ref()))?;
+    let mut i = 0;
+    while i < factors.len() {
+        let p = &factors[i];
+        let multiplicity = factors[i..].iter().take_while(|q| *q == p).count();
+        if divisors.len() * (multiplicity + 1) > MAX_ROOT_CANDIDATES {
+            return Err(Undefined::TooLarge);
+        }
+        let n_divisors = divisors.len();
+        for k in 1..=multiplicity as u64 {
+            let p_k = p.pow(k);
+            for j in 0..n_divisors {
+                divisors.push(&divisors[j] * &p_k);
+            }
+        }
+        i += multiplicity;
+    }
+    Ok(divisors)
+}
+
+// Applies an operation to two polynomials, or a polynomial and a number.
+pub fn binary_op<T: Into<Value>>(
+    left: &Value,
+    right: &Value,
+    op: fn(&Polynomial, &Polynomial) -> T,
+) -> Value {
+    let to_polynomial = |value: &Value| match value {
+        Value::Poly(p) => p.clone(),
+        Value::Number(n) => Polynomial::constant(n.clone()),
+        _ => panic!("polynomial operation on a {value:?}"),
+    };
+    op(&to_polynomial(left), &to_polynomial(right)).into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn poly(coefficients: &[i64]) -> Polynomial {
+        Polynomial::new(coefficients.iter().map(|&c| Rational::from(c)).collect())
+    }
+
+    #[test]
+    fn arithmetic_test() {
+        let a = poly(&[-1, 0, 1]);
+        let b = poly(&[1, 1]);
+        assert_eq!(a.add(&b), poly(&[0, 1, 1]));
+        assert_eq!(a.sub(&poly(&[0, 0, 1])), poly(&[-1]));
+        assert_eq!(a.mul(&b), poly(&[-1, -1, 1, 1]));
+        assert_eq!(a.div(&b).unwrap(), poly(&[-1, 1]));
+        assert_eq!(b.div(&a), Err(Undefined::NotPolynomial));
+        assert_eq!(b.pow(&Rational::from(3)).unwrap(), poly(&[1, 3, 3, 1]));
+        assert_eq!(b.pow(&Rational::from(100_000)), Err(Undefined::TooLarge));
+        let x_10000 = poly(&[0, 1]).pow(&Rational::from(10_000)).unwrap();
+        assert_eq!(x_10000.degree(), Some(10_000));
+        assert_eq!(a.derivative(), poly(&[0, 2]));
+        assert_eq!(a.eval(&Rational::from(3)), 8);
+    }
+
+    #[test]
+    fn div_rem_test() {
+        let (q, r) = poly(&[1, 2, 3, 4]).div_rem(&poly(&[1, 2])).unwrap();
+        assert_eq!(q.mul(&poly(&[1, 2])).add(&r), poly(&[1, 2, 3, 4]));
+        assert!(r.degree().is_none() || r.degree() < Some(1));
+        assert!(poly(&[1]).div_rem(&poly(&[])).is_err());
+    }
+
+    #[test]
+    fn gcd_test() {
+        let a = poly(&[-1, 0, 1]).mul(&poly(&[2, 1]));
+        let b = poly(&[1, 1]).mul(&poly(&[3, 1]));
+        assert_eq!(a.gcd(&b), poly(&[1, 1]));
+        assert_eq!(
+            poly(&[2, 4]).gcd(&poly(&[])),
+            poly(&[1, 2]).scale(&Rational::from_signeds(1, 2))
+        );
+    }
+
+    #[test]
+    fn rational_roots_test() {
+        // (2X - 1)(X + 3)(X^2 - 2) X
+        let p = poly(&[-1, 2])
+            .mul(&poly(&[3, 1]))
+            .mul(&poly(&[-2, 0, 1]))
+            .mul(&poly(&[0, 1]));
+        assert_eq!(
+            p.rational_roots().unwrap(),
+            [
+                Rational::from(-3),
+                Rational::ZERO,
+                Rational::from_signeds(1, 2)
+            ]
+        );
+        assert!(poly(&[1, 0, 1]).rational_roots().unwrap().is_empty());
+        assert!(poly(&[]).rational_roots().is_err());
+        // The constant is a semiprime too large to factor, but the polynomial is linear.
+        let semiprime =
+            ((Integer::ONE << 89) - Integer::ONE) * ((Integer::ONE << 107) - Integer::ONE);
+        let linear = Polynomial::new(vec![Rational::from(-&semiprime), Rational::ONE]);
+        assert_eq!(
+            linear.rational_roots().unwrap(),
+            [Rational::from(semiprime)]
+        );
+        // After dividing out the roots 1 and 2, the rest is linear.
+        let p = poly(&[-3, 2]).mul(&poly(&[-1, 1])).mul(&poly(&[-2, 1]));
+        assert_eq!(
+            p.rational_roots().unwrap(),
+            [
+                Rational::ONE,
+                Rational::from_signeds(3, 2),
+                Rational::from(2)
+            ]
+        );
+        // The product of the first 17 primes has 2^17 divisors, too many to try them all.
+        let primorial = [
+            2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59,
+        ]
+        .into_iter()
+        .fold(Integer::ONE, |product, p| product * Integer::from(p));
+        let highly_composite = Polynomial::new(vec![
+            Rational::from(primorial),
+            Rational::ONE,
+            Rational::ZERO,
+            Rational::ONE,
+        ]);
+        assert_eq!(highly_composite.rational_roots(), Err(Undefined::TooLarge));
+    }
+}
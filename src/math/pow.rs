@@ -1,93 +1,118 @@
-use malachite::num::arithmetic::traits::{Pow, Reciprocal, UnsignedAbs};
+use crate::math::value::Undefined;
+use malachite::num::arithmetic::traits::{CheckedRoot, Gcd, ModPow, Parity, Pow, Reciprocal};
 use malachite::num::basic::traits::{One, Zero};
-use malachite::num::logic::traits::BitAccess;
+use malachite::num::logic::traits::{BitAccess, SignificantBits};
 use malachite::{Integer, Natural, Rational};
 use std::cmp::Ordering;
+use std::sync::atomic::{self, AtomicU64};
 
-fn nat_pow(b: &Natural, e: &Natural) -> Natural {
+pub const DEFAULT_MAX_BITS: u64 = 1 << 26;
+
+// Powers whose numerator or denominator would have more bits than this are too large to compute.
+static MAX_BITS: AtomicU64 = AtomicU64::new(DEFAULT_MAX_BITS);
+
+pub fn set_max_bits(max_bits: u64) {
+    MAX_BITS.store(max_bits, atomic::Ordering::Relaxed);
+}
+
+pub fn max_bits() -> u64 {
+    MAX_BITS.load(atomic::Ordering::Relaxed)
+}
+
+fn nat_pow(b: &Natural, e: &Natural) -> Result<Natural, Undefined> {
     if *b == 0 {
-        return if *e == 0 { Natural::ONE } else { Natural::ZERO };
+        return Ok(if *e == 0 { Natural::ONE } else { Natural::ZERO });
     }
     if *b == 1 {
-        return Natural::ONE;
+        return Ok(Natural::ONE);
     }
-    if let Ok(e) = u64::try_from(e) {
-        return b.pow(e);
+    // b^e has at most bits(b) * e bits.
+    let e = u64::try_from(e).map_err(|_| Undefined::TooLarge)?;
+    match b.significant_bits().checked_mul(e) {
+        Some(bits) if bits <= max_bits() => Ok(b.pow(e)),
+        _ => Err(Undefined::TooLarge),
     }
-    panic!("out of memory");
 }
 
-fn rat_nat_pow(mut b: Rational, e: &Natural) -> Rational {
+fn rat_nat_pow(mut b: Rational, e: &Natural) -> Result<Rational, Undefined> {
     if b < 0 && !e.get_bit(0) {
         b = -b;
     }
-    b.mutate_numerator_and_denominator(|numer, denom| {
-        *numer = nat_pow(numer, e);
-        *denom = nat_pow(denom, e);
-    });
-    b
+    let sign = b >= 0;
+    let (numer, denom) = b.into_numerator_and_denominator();
+    Ok(Rational::from_sign_and_naturals(
+        sign,
+        nat_pow(&numer, e)?,
+        nat_pow(&denom, e)?,
+    ))
 }
 
-fn rat_int_pow(mut b: Rational, e: &Integer) -> Option<Rational> {
-    b = rat_nat_pow(b, e.unsigned_abs_ref());
+fn rat_int_pow(mut b: Rational, e: &Integer) -> Result<Rational, Undefined> {
+    b = rat_nat_pow(b, e.unsigned_abs_ref())?;
     if *e >= 0 {
-        return Some(b);
+        return Ok(b);
     }
     if b == 0 {
-        return None;
+        return Err(Undefined::Infinity);
     }
-    Some(b.reciprocal())
+    Ok(b.reciprocal())
 }
 
-fn nat_root(n: Natural, x: Natural) -> Option<Natural> {
-    use std::cmp::Ordering::*;
-    debug_assert_ne!(n, 0);
-    debug_assert_ne!(x, 0);
-    let mut lower = Natural::ZERO;
-    let mut upper = x.clone();
-    loop {
-        let avg = (&lower + &upper) >> 1;
-        if lower == avg {
-            break;
-        }
-        let pow = nat_pow(&avg, &n);
-        match pow.cmp(&x) {
-            Less => lower = avg,
-            Equal => return Some(avg),
-            Greater => upper = avg,
-        }
+const SMALL_PRIMES: [u64; 24] = [
+    3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+];
+
+// Returns false if x is certainly not an n'th power.
+// An n'th power has a number of trailing zeros divisible by n,
+// and for every prime p it's either divisible by p or an n'th power residue modulo p.
+fn may_be_nth_power(x: &Natural, n: u64) -> bool {
+    if !x.trailing_zeros().unwrap().is_multiple_of(n) {
+        return false;
     }
-    if nat_pow(&lower, &n) == x {
-        return Some(lower);
+    SMALL_PRIMES.into_iter().all(|p| {
+        let g = n.gcd(p - 1);
+        if g == 1 {
+            // Every number is an n'th power residue modulo p.
+            return true;
+        }
+        let r = u64::try_from(&(x % Natural::from(p))).unwrap();
+        r == 0 || r.mod_pow((p - 1) / g, p) == 1
+    })
+}
+
+// Returns the n'th root of x if it's an integer.
+fn nat_root(n: &Natural, x: &Natural) -> Option<Natural> {
+    if *x <= 1 {
+        return Some(x.clone());
     }
-    if nat_pow(&upper, &n) == x {
-        return Some(upper);
+    // The root of x is at least 2, so 2^n <= x.
+    let n = u64::try_from(n)
+        .ok()
+        .filter(|&n| n < x.significant_bits())?;
+    if !may_be_nth_power(x, n) {
+        return None;
     }
-    None
+    x.checked_root(n)
 }
 
-fn nth_root(n: Natural, x: Integer) -> Option<Integer> {
-    debug_assert_ne!(n, 0);
-    let x_sign = x.partial_cmp(&0).unwrap();
-    let x_abs = x.unsigned_abs();
-    Some(match x_sign {
-        Ordering::Greater => Integer::from(nat_root(n, x_abs)?),
-        Ordering::Less => {
-            if !n.get_bit(0) {
-                return None;
-            }
-            -Integer::from(nat_root(n, x_abs)?)
-        }
-        Ordering::Equal => unimplemented!(),
-    })
+fn nth_root(n: &Natural, x: &Integer) -> Option<Integer> {
+    debug_assert_ne!(*n, 0);
+    let root = Integer::from(nat_root(n, x.unsigned_abs_ref())?);
+    if *x >= 0 {
+        Some(root)
+    } else if n.odd() {
+        Some(-root)
+    } else {
+        None
+    }
 }
 
-pub fn pow(mut a: Rational, b: Rational) -> Option<Rational> {
+pub fn pow(mut a: Rational, b: Rational) -> Result<Rational, Undefined> {
     if a == 0 {
         return match Rational::partial_cmp(&b, &0).unwrap() {
-            Ordering::Less => None,
-            Ordering::Equal => Some(Rational::ONE),
-            Ordering::Greater => Some(Rational::ZERO),
+            Ordering::Less => Err(Undefined::Infinity),
+            Ordering::Equal => Ok(Rational::ONE),
+            Ordering::Greater => Ok(Rational::ZERO),
         };
     }
     let b_sign = b > 0;
@@ -96,8 +121,9 @@ pub fn pow(mut a: Rational, b: Rational) -> Option<Rational> {
     if root != 1 {
         let a_sign = a > 0;
         let (numer, denom) = a.into_numerator_and_denominator();
-        let numer = nth_root(root.clone(), Integer::from_sign_and_abs(a_sign, numer))?;
-        let denom = nth_root(root, Integer::from(denom))?;
+        let numer = nth_root(&root, &Integer::from_sign_and_abs(a_sign, numer))
+            .ok_or(Undefined::Irrational)?;
+        let denom = nth_root(&root, &Integer::from(denom)).ok_or(Undefined::Irrational)?;
         a = Rational::from_integers(numer, denom);
     }
     rat_int_pow(a, &pow)
@@ -123,7 +149,7 @@ mod test {
             None
         ) => {
             assert_eq!(
-                pow(frac!($numer1 $(/ $denom1)?), frac!($numer2 $(/ $denom2)?)),
+                pow(frac!($numer1 $(/ $denom1)?), frac!($numer2 $(/ $denom2)?)).ok(),
                 None,
                 stringify!(pow($numer1 $(/ $denom1)?, $numer2 $(/ $denom2)?))
             );
@@ -134,7 +160,7 @@ mod test {
             $numer3:literal $(/ $denom3:literal)?
         ) => {
             assert_eq!(
-                pow(frac!($numer1 $(/ $denom1)?), frac!($numer2 $(/ $denom2)?)),
+                pow(frac!($numer1 $(/ $denom1)?), frac!($numer2 $(/ $denom2)?)).ok(),
                 Some(frac!($numer3 $(/ $denom3)?)),
                 stringify!(pow($numer1 $(/ $denom1)?, $numer2 $(/ $denom2)?))
             );
@@ -204,4 +230,86 @@ mod test {
         assert_pow!(-9 / 16, -5 / 2, None);
         assert_pow!(-9 / 16, -5 / 3, None);
     }
+
+    #[test]
+    fn too_large_test() {
+        let ten = Rational::from(10);
+        assert!(matches!(
+            pow(ten.clone(), Rational::from(10).pow(20u64)),
+            Err(Undefined::TooLarge)
+        ));
+        assert!(matches!(
+            pow(
+                Rational::from_signeds(1, 3),
+                Rational::from(DEFAULT_MAX_BITS)
+            ),
+            Err(Undefined::TooLarge)
+        ));
+        assert!(pow(ten, Rational::from(1000)).is_ok());
+        // 3^(max - 1) has about 1.58 * max bits.
+        assert!(matches!(
+            pow(Rational::from(3), Rational::from(DEFAULT_MAX_BITS - 1)),
+            Err(Undefined::TooLarge)
+        ));
+        // Powers of 2 have at most 2 * e bits by the estimate.
+        assert!(pow(Rational::from(2), Rational::from(DEFAULT_MAX_BITS / 2)).is_ok());
+        assert!(matches!(
+            pow(Rational::from(2), Rational::from(DEFAULT_MAX_BITS / 2 + 1)),
+            Err(Undefined::TooLarge)
+        ));
+        assert!(matches!(
+            pow(Rational::from(3), Rational::from_unsigneds(1u64, 1 << 40)),
+            Err(Undefined::Irrational)
+        ));
+    }
+
+    #[test]
+    fn big_root_test() {
+        let x = Natural::from(3u32).pow(5000) * Natural::from(7u32).pow(3001);
+        let x7 = Rational::from(x.clone().pow(7));
+        assert_eq!(
+            pow(x7.clone(), Rational::from_signeds(1, 7)),
+            Ok(Rational::from(x.clone()))
+        );
+        assert_eq!(
+            pow(x7.clone() + Rational::ONE, Rational::from_signeds(1, 7)),
+            Err(Undefined::Irrational)
+        );
+        assert_eq!(
+            pow(Rational::ONE / x7, Rational::from_signeds(-3, 7)),
+            Ok(Rational::from(x.pow(3)))
+        );
+    }
+
+    // Run with `cargo test --release pow_benchmark -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn pow_benchmark() {
+        use std::time::Instant;
+
+        let start = Instant::now();
+        for _ in 0..1000 {
+            pow_test();
+        }
+        println!("pow_test x1000: {:?}", start.elapsed());
+
+        for digits in [1000, 5000, 20000] {
+            for n in [2, 3, 7, 50] {
+                // The n'th power of x has about `digits` digits.
+                let x = Natural::from(10u32).pow(digits / n) + Natural::from(12345u32);
+                let power = Rational::from(x.clone().pow(n));
+                let not_power = &power + Rational::ONE;
+                let root = Rational::from_unsigneds(1u64, n);
+                let start = Instant::now();
+                assert_eq!(pow(power, root.clone()), Ok(Rational::from(x.clone())));
+                let exact = start.elapsed();
+                let start = Instant::now();
+                assert_eq!(pow(not_power, root), Err(Undefined::Irrational));
+                let inexact = start.elapsed();
+                println!(
+                    "{digits} digits, root {n}: {exact:?} for a perfect power, {inexact:?} otherwise"
+                );
+            }
+        }
+    }
 }
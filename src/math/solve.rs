@@ -0,0 +1,216 @@
+use crate::math::number_theory::simplest_between;
+use crate::math::{Undefined, Value};
+use malachite::num::arithmetic::traits::Abs;
+use malachite::num::basic::traits::One;
+use malachite::Rational;
+use std::cmp::Ordering;
+
+// The default maximum distance between the result and a root.
+pub fn default_tolerance() -> Rational {
+    Rational::from_unsigneds(1u64, 1_000_000_000_000)
+}
+
+// The largest number of times the search interval around a starting point is doubled.
+const MAX_DOUBLINGS: u64 = 80;
+
+fn number(value: &Value) -> Result<&Rational, Undefined> {
+    match value {
+        Value::Number(n) => Ok(n),
+        Value::Undefined(u) => Err(u.clone()),
+        _ => Err(Undefined::TypeMismatch),
+    }
+}
+
+fn sign(value: &Value) -> Result<Ordering, Undefined> {
+    number(value).map(|n| n.partial_cmp(&0).unwrap())
+}
+
+// Finds a root of f between a and b, if f has different signs at a and b.
+// The result is within the tolerance of a root, or exactly a root if f is exactly 0 there.
+// Each step picks the simplest fraction in the middle half of the interval,
+// so the numbers stay small and simple roots are found exactly.
+// If f doesn't shrink as the interval narrows, the sign change is a jump, like floor(x) - 1/2 at 1,
+// or a pole, like 1/x at 0, and not a root.
+pub fn bisect<E>(
+    f: &mut impl FnMut(&Rational) -> Result<Value, E>,
+    a: Rational,
+    b: Rational,
+    tolerance: &Rational,
+) -> Result<Value, E> {
+    let (mut a, mut b) = if a <= b { (a, b) } else { (b, a) };
+    let value_a = f(&a)?;
+    let sign_a = match sign(&value_a) {
+        Ok(Ordering::Equal) => return Ok(Value::Number(a)),
+        Ok(sign) => sign,
+        Err(u) => return Ok(Value::Undefined(u)),
+    };
+    let value_b = f(&b)?;
+    let sign_b = match sign(&value_b) {
+        Ok(Ordering::Equal) => return Ok(Value::Number(b)),
+        Ok(sign) => sign,
+        Err(u) => return Ok(Value::Undefined(u)),
+    };
+    if sign_a == sign_b {
+        return Ok(Value::Undefined(Undefined::NoSignChange));
+    }
+    let magnitude = |value: &Value| number(value).unwrap().abs();
+    let (mut magnitude_a, mut magnitude_b) = (magnitude(&value_a), magnitude(&value_b));
+    // The width of the interval and the largest magnitude of f at its ends, after each step.
+    let mut history = vec![(&b - &a, (&magnitude_a).max(&magnitude_b).clone())];
+    // There's a root between a and b, so every number between them is close enough once they're close enough.
+    while &b - &a > *tolerance {
+        let quarter = (&b - &a) / Rational::from(4);
+        let middle = simplest_between(&(&a + &quarter), &(&b - &quarter));
+        let value = f(&middle)?;
+        match sign(&value) {
+            Ok(Ordering::Equal) => return Ok(Value::Number(middle)),
+            Ok(sign) if sign == sign_a => (a, magnitude_a) = (middle, magnitude(&value)),
+            Ok(_) => (b, magnitude_b) = (middle, magnitude(&value)),
+            Err(u) => return Ok(Value::Undefined(u)),
+        }
+        history.push((&b - &a, (&magnitude_a).max(&magnitude_b).clone()));
+    }
+    // Near a root, f shrinks with the interval: even x^(1/5) more than halves when
+    // the interval narrows 1024 times. Near a jump, f stays the same, and near a pole, it grows.
+    let (width, last_magnitude) = history.last().unwrap();
+    let earlier_magnitude = match history
+        .iter()
+        .rev()
+        .find(|(w, _)| *w >= width * Rational::from(1024))
+    {
+        Some((_, earlier)) => earlier / Rational::from(2),
+        None => history[0].1.clone(),
+    };
+    if *last_magnitude > earlier_magnitude {
+        return Ok(Value::Undefined(Undefined::NoRoot));
+    }
+    Ok(Value::Number(simplest_between(&a, &b)))
+}
+
+// Finds a root of f near x, by searching for a sign change in wider and wider intervals around x,
+// and then bisecting.
+pub fn solve_near<E>(
+    f: &mut impl FnMut(&Rational) -> Result<Value, E>,
+    x: Rational,
+    tolerance: &Rational,
+) -> Result<Value, E> {
+    let sign_x = match sign(&f(&x)?) {
+        Ok(Ordering::Equal) => return Ok(Value::Number(x)),
+        Ok(sign) => sign,
+        Err(u) => return Ok(Value::Undefined(u)),
+    };
+    let mut step = Rational::ONE.max((&x).abs()) / Rational::from(1024);
+    for _ in 0..MAX_DOUBLINGS {
+        for y in [&x - &step, &x + &step] {
+            // f may be undefined at some distance from x, and defined further away.
+            if let Ok(sign_y) = sign(&f(&y)?) {
+                if sign_y != sign_x {
+                    return bisect(f, x, y, tolerance);
+                }
+            }
+        }
+        step *= Rational::from(2);
+    }
+    Ok(Value::Undefined(Undefined::NoSignChange))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use malachite::num::arithmetic::traits::Floor;
+    use std::convert::Infallible;
+
+    fn frac(numer: i64, denom: i64) -> Rational {
+        Rational::from_signeds(numer, denom)
+    }
+
+    fn number(value: Value) -> Rational {
+        match value {
+            Value::Number(n) => n,
+            value => panic!("{value}"),
+        }
+    }
+
+    #[test]
+    fn bisect_test() {
+        let tolerance = frac(1, 1_000_000);
+        let mut square_minus_2 =
+            |x: &Rational| Ok::<_, Infallible>(Value::Number(x * x - Rational::from(2)));
+        let root = number(
+            bisect(
+                &mut square_minus_2,
+                Rational::from(0),
+                Rational::from(5),
+                &tolerance,
+            )
+            .unwrap(),
+        );
+        // The root is between root - tolerance and root + tolerance.
+        assert!(number(square_minus_2(&(&root - &tolerance)).unwrap()) < 0);
+        assert!(number(square_minus_2(&(&root + &tolerance)).unwrap()) > 0);
+        let mut cubic = |x: &Rational| Ok::<_, Infallible>(Value::Number((x - frac(3, 7)) * x * x));
+        assert_eq!(
+            number(bisect(&mut cubic, frac(1, 10), Rational::from(1), &tolerance).unwrap()),
+            frac(3, 7)
+        );
+        assert!(matches!(
+            bisect(
+                &mut square_minus_2,
+                Rational::from(2),
+                Rational::from(5),
+                &tolerance
+            ),
+            Ok(Value::Undefined(Undefined::NoSignChange))
+        ));
+    }
+
+    #[test]
+    fn pole_test() {
+        let tolerance = frac(1, 1_000_000);
+        // 1/(x^2 - 2) changes sign at the pole sqrt(2).
+        let mut pole = |x: &Rational| {
+            Ok::<_, Infallible>(Value::Number(Rational::ONE / (x * x - Rational::from(2))))
+        };
+        assert!(matches!(
+            bisect(&mut pole, Rational::from(1), Rational::from(2), &tolerance),
+            Ok(Value::Undefined(Undefined::NoRoot))
+        ));
+        assert!(matches!(
+            solve_near(&mut pole, Rational::from(1), &tolerance),
+            Ok(Value::Undefined(Undefined::NoRoot))
+        ));
+        // Neither does a jump.
+        let mut jump = |x: &Rational| {
+            Ok::<_, Infallible>(Value::Number(Rational::from(x.floor()) - frac(1, 2)))
+        };
+        assert!(matches!(
+            bisect(&mut jump, Rational::from(0), Rational::from(3), &tolerance),
+            Ok(Value::Undefined(Undefined::NoRoot))
+        ));
+        // A steep function still has a root.
+        let mut steep = |x: &Rational| {
+            Ok::<_, Infallible>(Value::Number(
+                (x - frac(1, 3)) * Rational::from(10u64.pow(15)),
+            ))
+        };
+        assert_eq!(
+            number(bisect(&mut steep, Rational::from(0), Rational::from(1), &tolerance).unwrap()),
+            frac(1, 3)
+        );
+    }
+
+    #[test]
+    fn solve_near_test() {
+        let tolerance = frac(1, 1_000_000_000);
+        let mut f = |x: &Rational| Ok::<_, Infallible>(Value::Number(x - Rational::from(1000)));
+        assert_eq!(
+            number(solve_near(&mut f, Rational::from(-3), &tolerance).unwrap()),
+            1000
+        );
+        let mut g = |x: &Rational| Ok::<_, Infallible>(Value::Number(x * x + Rational::ONE));
+        assert!(matches!(
+            solve_near(&mut g, Rational::from(0), &tolerance),
+            Ok(Value::Undefined(Undefined::NoSignChange))
+        ));
+    }
+}
@@ -0,0 +1,404 @@
+use crate::math::value::{Undefined, Value};
+use malachite::num::arithmetic::traits::Pow;
+use malachite::num::basic::traits::One;
+use malachite::Rational;
+use once_cell::sync::Lazy;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+// The exponents of the SI base units: metre, kilogram, second, ampere, kelvin, mole and candela.
+type Dimension = [i64; 7];
+
+#[derive(Debug)]
+pub struct Unit {
+    pub name: &'static str,
+    factor: Rational, // The size of the unit in SI base units.
+    dimension: Dimension,
+}
+
+const LENGTH: Dimension = [1, 0, 0, 0, 0, 0, 0];
+const MASS: Dimension = [0, 1, 0, 0, 0, 0, 0];
+const TIME: Dimension = [0, 0, 1, 0, 0, 0, 0];
+const CURRENT: Dimension = [0, 0, 0, 1, 0, 0, 0];
+const TEMPERATURE: Dimension = [0, 0, 0, 0, 1, 0, 0];
+const AMOUNT: Dimension = [0, 0, 0, 0, 0, 1, 0];
+const LUMINOSITY: Dimension = [0, 0, 0, 0, 0, 0, 1];
+const AREA: Dimension = [2, 0, 0, 0, 0, 0, 0];
+const VOLUME: Dimension = [3, 0, 0, 0, 0, 0, 0];
+const SPEED: Dimension = [1, 0, -1, 0, 0, 0, 0];
+const FREQUENCY: Dimension = [0, 0, -1, 0, 0, 0, 0];
+const FORCE: Dimension = [1, 1, -2, 0, 0, 0, 0];
+const PRESSURE: Dimension = [-1, 1, -2, 0, 0, 0, 0];
+const ENERGY: Dimension = [2, 1, -2, 0, 0, 0, 0];
+const POWER: Dimension = [2, 1, -3, 0, 0, 0, 0];
+const CHARGE: Dimension = [0, 0, 1, 1, 0, 0, 0];
+const VOLTAGE: Dimension = [2, 1, -3, -1, 0, 0, 0];
+const RESISTANCE: Dimension = [2, 1, -3, -2, 0, 0, 0];
+
+// The name, size in SI base units, and dimension of every unit.
+// All the sizes are exact, by the definitions of the units.
+const UNIT_TABLE: &[(&str, &str, Dimension)] = &[
+    ("m", "1", LENGTH),
+    ("km", "1000", LENGTH),
+    ("cm", "1/100", LENGTH),
+    ("mm", "1/1000", LENGTH),
+    ("μm", "1/1000000", LENGTH),
+    ("nm", "1/1000000000", LENGTH),
+    ("inch", "127/5000", LENGTH),
+    ("ft", "381/1250", LENGTH),
+    ("yd", "1143/1250", LENGTH),
+    ("mile", "201168/125", LENGTH),
+    ("nmi", "1852", LENGTH),
+    ("au", "149597870700", LENGTH),
+    ("ly", "9460730472580800", LENGTH),
+    ("kg", "1", MASS),
+    ("g", "1/1000", MASS),
+    ("mg", "1/1000000", MASS),
+    ("tonne", "1000", MASS),
+    ("lb", "45359237/100000000", MASS),
+    ("oz", "45359237/1600000000", MASS),
+    ("s", "1", TIME),
+    ("ms", "1/1000", TIME),
+    ("μs", "1/1000000", TIME),
+    ("ns", "1/1000000000", TIME),
+    ("minute", "60", TIME),
+    ("h", "3600", TIME),
+    ("day", "86400", TIME),
+    ("week", "604800", TIME),
+    ("year", "31557600", TIME), // A Julian year of 365.25 days.
+    ("A", "1", CURRENT),
+    ("mA", "1/1000", CURRENT),
+    ("K", "1", TEMPERATURE),
+    ("mol", "1", AMOUNT),
+    ("cd", "1", LUMINOSITY),
+    ("ha", "10000", AREA),
+    ("L", "1/1000", VOLUME),
+    ("mL", "1/1000000", VOLUME),
+    ("mph", "1397/3125", SPEED),
+    ("Hz", "1", FREQUENCY),
+    ("N", "1", FORCE),
+    ("kN", "1000", FORCE),
+    ("Pa", "1", PRESSURE),
+    ("kPa", "1000", PRESSURE),
+    ("bar", "100000", PRESSURE),
+    ("atm", "101325", PRESSURE),
+    ("J", "1", ENERGY),
+    ("kJ", "1000", ENERGY),
+    ("cal", "523/125", ENERGY),
+    ("kcal", "4184", ENERGY),
+    ("Wh", "3600", ENERGY),
+    ("kWh", "3600000", ENERGY),
+    ("eV", "1602176634/10000000000000000000000000000", ENERGY),
+    ("W", "1", POWER),
+    ("kW", "1000", POWER),
+    ("C", "1", CHARGE),
+    ("V", "1", VOLTAGE),
+    ("Ω", "1", RESISTANCE),
+];
+
+static UNITS: Lazy<Vec<Unit>> = Lazy::new(|| {
+    UNIT_TABLE
+        .iter()
+        .map(|&(name, factor, dimension)| Unit {
+            name,
+            factor: Rational::from_str(factor).unwrap(),
+            dimension,
+        })
+        .collect()
+});
+
+pub fn units() -> &'static [Unit] {
+    &UNITS
+}
+
+// A number with units, e.g. 9.81 m/s^2.
+// The units are kept as they were written, so 3 km + 500 m is 3.5 km.
+// A product of units of the same dimension isn't simplified, so (3 km)*(2 m) is 6 km*m.
+#[derive(Clone, Debug)]
+pub struct Quantity {
+    value: Rational,
+    units: Vec<(&'static Unit, i64)>, // Each unit at most once, with a nonzero exponent.
+}
+
+impl Quantity {
+    pub fn unit(unit: &'static Unit) -> Self {
+        Self {
+            value: Rational::ONE,
+            units: vec![(unit, 1)],
+        }
+    }
+
+    pub fn number(value: Rational) -> Self {
+        Self {
+            value,
+            units: vec![],
+        }
+    }
+
+    fn dimension(&self) -> Dimension {
+        let mut dimension = [0; 7];
+        for (unit, exponent) in &self.units {
+            for (d, u) in dimension.iter_mut().zip(unit.dimension) {
+                *d += u * exponent;
+            }
+        }
+        dimension
+    }
+
+    // The size of the units in SI base units.
+    fn factor(&self) -> Rational {
+        self.units
+            .iter()
+            .map(|(unit, exponent)| (&unit.factor).pow(*exponent))
+            .product()
+    }
+
+    fn si_value(&self) -> Rational {
+        &self.value * self.factor()
+    }
+
+    // The value of the other quantity in the units of this one.
+    fn value_in_units_of(&self, other: &Self) -> Result<Rational, Undefined> {
+        if self.dimension() != other.dimension() {
+            return Err(Undefined::DimensionMismatch);
+        }
+        Ok(other.si_value() / self.factor())
+    }
+
+    pub fn add(&self, other: &Self) -> Result<Self, Undefined> {
+        // 0 has every dimension.
+        if self.units.is_empty() && self.value == 0 {
+            return Ok(other.clone());
+        }
+        if other.units.is_empty() && other.value == 0 {
+            return Ok(self.clone());
+        }
+        Ok(Self {
+            value: &self.value + self.value_in_units_of(other)?,
+            units: self.units.clone(),
+        })
+    }
+
+    pub fn neg(&self) -> Self {
+        Self {
+            value: -&self.value,
+            units: self.units.clone(),
+        }
+    }
+
+    pub fn sub(&self, other: &Self) -> Result<Self, Undefined> {
+        self.add(&other.neg())
+    }
+
+    pub fn abs(&self) -> Self {
+        if self.value < 0 {
+            self.neg()
+        } else {
+            self.clone()
+        }
+    }
+
+    fn with_units(&self, other: &Self, sign: i64) -> Vec<(&'static Unit, i64)> {
+        let mut units = self.units.clone();
+        for &(unit, exponent) in &other.units {
+            match units.iter_mut().find(|(u, _)| u.name == unit.name) {
+                Some((_, e)) => *e += sign * exponent,
+                None => units.push((unit, sign * exponent)),
+            }
+        }
+        units.retain(|&(_, exponent)| exponent != 0);
+        units
+    }
+
+    pub fn mul(&self, other: &Self) -> Self {
+        Self {
+            value: &self.value * &other.value,
+            units: self.with_units(other, 1),
+        }
+    }
+
+    pub fn div(&self, other: &Self) -> Result<Self, Undefined> {
+        if other.value == 0 {
+            return Err(if self.value == 0 {
+                Undefined::ZeroOverZero
+            } else {
+                Undefined::Infinity
+            });
+        }
+        Ok(Self {
+            value: &self.value / &other.value,
+            units: self.with_units(other, -1),
+        })
+    }
+
+    // A rational power is only defined if it gives whole powers of the units, e.g. (4 m^2)^(1/2) = 2 m.
+    pub fn pow(&self, exponent: &Rational) -> Result<Self, Undefined> {
+        let numerator = i64::try_from(exponent.numerator_ref()).map_err(|_| Undefined::TooLarge)?;
+        let numerator = if *exponent < 0 { -numerator } else { numerator };
+        let denominator =
+            i64::try_from(exponent.denominator_ref()).map_err(|_| Undefined::DimensionMismatch)?;
+        let units = self
+            .units
+            .iter()
+            .map(|&(unit, e)| {
+                let e = e.checked_mul(numerator).ok_or(Undefined::TooLarge)?;
+                if e % denominator != 0 {
+                    return Err(Undefined::DimensionMismatch);
+                }
+                Ok((unit, e / denominator))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            value: super::pow(self.value.clone(), exponent.clone())?,
+            units,
+        })
+    }
+
+    // The same quantity in other units, e.g. 1 mile in km is 1.609344 km.
+    pub fn convert(&self, units: &Self) -> Result<Self, Undefined> {
+        if units.value != 1 {
+            return Err(Undefined::OutOfDomain);
+        }
+        if self.units.is_empty() && self.value == 0 {
+            return Ok(Self {
+                value: self.value.clone(),
+                units: units.units.clone(),
+            });
+        }
+        Ok(Self {
+            value: units.value_in_units_of(self)?,
+            units: units.units.clone(),
+        })
+    }
+
+    pub fn value(&self) -> &Rational {
+        &self.value
+    }
+
+    // The name and exponent of each unit, e.g. (m, 1) and (s, -2) for m/s^2.
+    pub fn unit_powers(&self) -> impl Iterator<Item = (&'static str, i64)> + '_ {
+        self.units
+            .iter()
+            .map(|&(unit, exponent)| (unit.name, exponent))
+    }
+
+    // Formats the units, e.g. kg*m/s^2.
+    pub fn units(&self) -> impl Display + '_ {
+        UnitsDisplay(&self.units)
+    }
+}
+
+struct UnitsDisplay<'a>(&'a [(&'static Unit, i64)]);
+
+impl Display for UnitsDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let write_unit = |f: &mut fmt::Formatter<'_>, unit: &Unit, exponent: i64| match exponent {
+            1 => write!(f, "{}", unit.name),
+            _ => write!(f, "{}^{exponent}", unit.name),
+        };
+        let (positive, negative): (Vec<_>, Vec<_>) = self.0.iter().partition(|(_, e)| *e > 0);
+        if positive.is_empty() {
+            // e.g. s^-1
+            for (i, &&(unit, exponent)) in negative.iter().enumerate() {
+                if i > 0 {
+                    write!(f, "*")?;
+                }
+                write_unit(f, unit, exponent)?;
+            }
+            return Ok(());
+        }
+        for (i, &&(unit, exponent)) in positive.iter().enumerate() {
+            if i > 0 {
+                write!(f, "*")?;
+            }
+            write_unit(f, unit, exponent)?;
+        }
+        for &&(unit, exponent) in &negative {
+            write!(f, "/")?;
+            write_unit(f, unit, -exponent)?;
+        }
+        Ok(())
+    }
+}
+
+// A quantity without units is just a number, and so is a quantity whose units cancel out, e.g. 3 km/m = 3000.
+impl From<Quantity> for Value {
+    fn from(q: Quantity) -> Self {
+        if q.units.is_empty() {
+            Value::Number(q.value)
+        } else if q.dimension() == [0; 7] {
+            Value::Number(q.si_value())
+        } else {
+            Value::Quantity(q)
+        }
+    }
+}
+
+// Applies an operation to two quantities, or a quantity and a number.
+pub fn binary_op<T: Into<Value>>(
+    left: &Value,
+    right: &Value,
+    op: fn(&Quantity, &Quantity) -> T,
+) -> Value {
+    let to_quantity = |value: &Value| match value {
+        Value::Quantity(q) => q.clone(),
+        Value::Number(n) => Quantity::number(n.clone()),
+        _ => panic!("quantity operation on a {value:?}"),
+    };
+    op(&to_quantity(left), &to_quantity(right)).into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn unit(name: &str) -> Quantity {
+        Quantity::unit(units().iter().find(|u| u.name == name).unwrap())
+    }
+
+    fn number(n: i64) -> Quantity {
+        Quantity::number(Rational::from(n))
+    }
+
+    #[test]
+    fn arithmetic_test() {
+        let km = unit("km");
+        let m = unit("m");
+        let s = unit("s");
+        let three_km = number(3).mul(&km);
+        let sum = three_km.add(&number(500).mul(&m)).unwrap();
+        assert_eq!(sum.value, Rational::from_signeds(7, 2));
+        assert_eq!(sum.units().to_string(), "km");
+        assert!(matches!(
+            three_km.add(&s),
+            Err(Undefined::DimensionMismatch)
+        ));
+        let acceleration = m.div(&s).unwrap().div(&s).unwrap();
+        assert_eq!(acceleration.units().to_string(), "m/s^2");
+        assert_eq!(
+            s.pow(&Rational::from(-1)).unwrap().units().to_string(),
+            "s^-1"
+        );
+        assert!(matches!(
+            Value::from(km.div(&m).unwrap()),
+            Value::Number(n) if n == 1000
+        ));
+        let area = number(4).mul(&m).mul(&m);
+        let side = area.pow(&Rational::from_signeds(1, 2)).unwrap();
+        assert_eq!(side.value, 2);
+        assert_eq!(side.units().to_string(), "m");
+        assert!(m.pow(&Rational::from_signeds(1, 2)).is_err());
+    }
+
+    #[test]
+    fn convert_test() {
+        let mile = unit("mile");
+        let km = unit("km");
+        let converted = mile.convert(&km).unwrap();
+        assert_eq!(converted.value, Rational::from_signeds(201168, 125000));
+        let speed = km.div(&unit("h")).unwrap();
+        let converted = number(10).mul(&unit("m").div(&unit("s")).unwrap());
+        assert_eq!(converted.convert(&speed).unwrap().value, 36);
+        assert!(mile.convert(&unit("s")).is_err());
+    }
+}
@@ -1,9 +1,16 @@
+use crate::expression::Expression;
+use crate::math::format::{Format, FormattedValue};
+use crate::math::list::{self, List};
+use crate::math::matrix::Matrix;
+use crate::math::modular::{self, Residue};
+use crate::math::polynomial::{self, Polynomial};
+use crate::math::units::{self, Quantity};
 use malachite::num::basic::traits::{One, Zero};
-use malachite::Rational;
+use malachite::{Integer, Natural, Rational};
 use std::fmt::Display;
 use std::ops;
 
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
 pub enum Undefined {
     #[default]
     _Default,
@@ -11,6 +18,20 @@ pub enum Undefined {
     Infinity,
     Irrational,
     InfiniteLoop,
+    TypeMismatch,
+    SizeMismatch,
+    IndexOutOfRange,
+    Singular,
+    OutOfDomain,
+    TooLarge,
+    NotInvertible,
+    NoModularRoot,
+    ModulusMismatch,
+    NotPolynomial,
+    NoSignChange,
+    NoRoot,
+    NoConvergence,
+    DimensionMismatch,
 }
 
 impl Display for Undefined {
@@ -22,6 +43,48 @@ impl Display for Undefined {
             Infinity => write!(f, "Undefined result: possibly infinite"),
             Irrational => write!(f, "Undefined result: possibly irrational"),
             InfiniteLoop => write!(f, "Undefined result: infinite loop detected"),
+            TypeMismatch => write!(f, "Undefined result: wrong type of value"),
+            SizeMismatch => write!(f, "Undefined result: mismatched sizes"),
+            IndexOutOfRange => write!(f, "Undefined result: index out of range"),
+            Singular => write!(f, "Undefined result: singular matrix"),
+            OutOfDomain => write!(f, "Undefined result: argument out of the function's domain"),
+            TooLarge => write!(f, "Undefined result: too large to compute"),
+            NotInvertible => write!(f, "Undefined result: no modular inverse"),
+            NoModularRoot => write!(f, "Undefined result: no modular root"),
+            ModulusMismatch => write!(f, "Undefined result: mismatched moduli"),
+            NotPolynomial => write!(f, "Undefined result: not a polynomial"),
+            NoSignChange => write!(f, "Undefined result: no sign change found"),
+            NoRoot => write!(f, "Undefined result: the sign change isn't a root"),
+            NoConvergence => write!(f, "Undefined result: the approximation didn't converge"),
+            DimensionMismatch => write!(f, "Undefined result: mismatched units"),
+        }
+    }
+}
+
+impl Undefined {
+    // A stable name for the reason, for machine-readable output.
+    pub fn code(&self) -> &'static str {
+        use self::Undefined::*;
+        match self {
+            _Default => panic!("Undefined::_Default"),
+            ZeroOverZero => "zero_over_zero",
+            Infinity => "infinity",
+            Irrational => "irrational",
+            InfiniteLoop => "infinite_loop",
+            TypeMismatch => "type_mismatch",
+            SizeMismatch => "size_mismatch",
+            IndexOutOfRange => "index_out_of_range",
+            Singular => "singular",
+            OutOfDomain => "out_of_domain",
+            TooLarge => "too_large",
+            NotInvertible => "not_invertible",
+            NoModularRoot => "no_modular_root",
+            ModulusMismatch => "modulus_mismatch",
+            NotPolynomial => "not_polynomial",
+            NoSignChange => "no_sign_change",
+            NoRoot => "no_root",
+            NoConvergence => "no_convergence",
+            DimensionMismatch => "dimension_mismatch",
         }
     }
 }
@@ -29,6 +92,11 @@ impl Display for Undefined {
 #[derive(Clone, Debug)]
 pub enum Value {
     Number(Rational),
+    List(List),
+    Matrix(Matrix),
+    Mod(Residue),
+    Poly(Polynomial),
+    Quantity(Quantity),
     Undefined(Undefined),
 }
 
@@ -46,6 +114,11 @@ impl ops::Neg for Value {
     fn neg(self) -> Self::Output {
         match self {
             Number(n) => Number(-n),
+            List(l) => List(l.map(Expression::Neg)),
+            Matrix(m) => Matrix(m.map(|n| -n)),
+            Mod(r) => Mod(r.neg()),
+            Poly(p) => Poly(p.neg()),
+            Quantity(q) => Quantity(q.neg()),
             Undefined(u) => Undefined(u),
         }
     }
@@ -65,8 +138,22 @@ impl ops::Add<&Self> for Value {
     fn add(self, rhs: &Self) -> Self::Output {
         match (self, rhs) {
             (Number(n), Number(m)) => Number(n + m),
+            (Matrix(a), Matrix(b)) => a.zip_with(b, |a, b| a + b).into(),
+            (left @ (Number(_) | Mod(_)), right @ (Number(_) | Mod(_))) => {
+                modular::binary_op(&left, right, Residue::add)
+            }
+            (left @ (Number(_) | Poly(_)), right @ (Number(_) | Poly(_))) => {
+                polynomial::binary_op(&left, right, Polynomial::add)
+            }
+            (left @ (Number(_) | Quantity(_)), right @ (Number(_) | Quantity(_))) => {
+                units::binary_op(&left, right, Quantity::add)
+            }
             (Undefined(u), _) => Undefined(u),
             (_, Undefined(u)) => Undefined(u.clone()),
+            (left @ List(_), right) | (left, right @ List(_)) => {
+                list::elementwise(&left, right, Expression::Add)
+            }
+            _ => Undefined(Undefined::TypeMismatch),
         }
     }
 }
@@ -77,8 +164,22 @@ impl ops::Sub<&Self> for Value {
     fn sub(self, rhs: &Self) -> Self::Output {
         match (self, rhs) {
             (Number(n), Number(m)) => Number(n - m),
+            (Matrix(a), Matrix(b)) => a.zip_with(b, |a, b| a - b).into(),
+            (left @ (Number(_) | Mod(_)), right @ (Number(_) | Mod(_))) => {
+                modular::binary_op(&left, right, Residue::sub)
+            }
+            (left @ (Number(_) | Poly(_)), right @ (Number(_) | Poly(_))) => {
+                polynomial::binary_op(&left, right, Polynomial::sub)
+            }
+            (left @ (Number(_) | Quantity(_)), right @ (Number(_) | Quantity(_))) => {
+                units::binary_op(&left, right, Quantity::sub)
+            }
             (Undefined(u), _) => Undefined(u),
             (_, Undefined(u)) => Undefined(u.clone()),
+            (left @ List(_), right) | (left, right @ List(_)) => {
+                list::elementwise(&left, right, Expression::Sub)
+            }
+            _ => Undefined(Undefined::TypeMismatch),
         }
     }
 }
@@ -89,10 +190,32 @@ impl ops::Mul<&Self> for Value {
     fn mul(self, rhs: &Self) -> Self::Output {
         match (self, rhs) {
             (Number(n), Number(m)) => Number(n * m),
-            (Number(z), Undefined(_)) if z == 0 => Number(Rational::ZERO),
-            (Undefined(_), Number(z)) if *z == 0 => Number(Rational::ZERO),
+            (Matrix(a), Matrix(b)) => a.mul(b).into(),
+            // Matrices times zero are zero matrices of the same size.
+            (Matrix(a), Number(n)) => Matrix(a.map(|m| m * n)),
+            (Number(n), Matrix(b)) => Matrix(b.clone().map(|m| &n * m)),
+            // Residues times zero keep their modulus.
+            (left @ (Number(_) | Mod(_)), right @ (Number(_) | Mod(_))) => {
+                modular::binary_op(&left, right, Residue::mul)
+            }
+            // Lists times zero are lists of zeros.
+            (left @ List(_), right) | (left, right @ List(_))
+                if !left.is_undefined() && !right.is_undefined() =>
+            {
+                list::elementwise(&left, right, Expression::Mul)
+            }
+            // Quantities times zero keep their units.
+            (left @ (Number(_) | Quantity(_)), right @ (Number(_) | Quantity(_))) => {
+                units::binary_op(&left, right, Quantity::mul)
+            }
+            (Number(z), _) if z == 0 => Number(Rational::ZERO),
+            (_, Number(z)) if *z == 0 => Number(Rational::ZERO),
+            (left @ (Number(_) | Poly(_)), right @ (Number(_) | Poly(_))) => {
+                polynomial::binary_op(&left, right, Polynomial::mul)
+            }
             (Undefined(u), _) => Undefined(u),
             (_, Undefined(u)) => Undefined(u.clone()),
+            _ => Undefined(Undefined::TypeMismatch),
         }
     }
 }
@@ -108,18 +231,84 @@ impl ops::Div<&Self> for Value {
                 Undefined::Infinity
             }),
             (Number(n), Number(m)) => Number(n / m),
+            (Matrix(_), Number(z)) if *z == 0 => Undefined(Undefined::Infinity),
+            (Matrix(a), Number(n)) => Matrix(a.map(|m| m / n)),
+            (left @ (Number(_) | Matrix(_)), Matrix(b)) => match b.inverse() {
+                Ok(inverse) => left * &Matrix(inverse),
+                Err(u) => Undefined(u),
+            },
+            (left @ (Number(_) | Mod(_)), right @ (Number(_) | Mod(_))) => {
+                modular::binary_op(&left, right, Residue::div)
+            }
+            (left @ (Number(_) | Poly(_)), right @ (Number(_) | Poly(_))) => {
+                polynomial::binary_op(&left, right, Polynomial::div)
+            }
+            (left @ (Number(_) | Quantity(_)), right @ (Number(_) | Quantity(_))) => {
+                units::binary_op(&left, right, Quantity::div)
+            }
             (Undefined(u), _) => Undefined(u),
             (_, Undefined(u)) => Undefined(u.clone()),
+            (left @ List(_), right) | (left, right @ List(_)) => {
+                list::elementwise(&left, right, Expression::Div)
+            }
+            _ => Undefined(Undefined::TypeMismatch),
         }
     }
 }
 
+impl<T: Into<Value>> From<Result<T, Undefined>> for Value {
+    fn from(result: Result<T, Undefined>) -> Self {
+        match result {
+            Ok(value) => value.into(),
+            Err(u) => Undefined(u),
+        }
+    }
+}
+
+impl From<Rational> for Value {
+    fn from(n: Rational) -> Self {
+        Number(n)
+    }
+}
+
+impl From<Integer> for Value {
+    fn from(n: Integer) -> Self {
+        Number(Rational::from(n))
+    }
+}
+
+impl From<Natural> for Value {
+    fn from(n: Natural) -> Self {
+        Number(Rational::from(n))
+    }
+}
+
+impl From<Residue> for Value {
+    fn from(r: Residue) -> Self {
+        Mod(r)
+    }
+}
+
+// Constant polynomials are just numbers.
+impl From<Polynomial> for Value {
+    fn from(p: Polynomial) -> Self {
+        match p.degree() {
+            None => Number(Rational::ZERO),
+            Some(0) => Number(p.coefficients()[0].clone()),
+            Some(_) => Poly(p),
+        }
+    }
+}
+
+impl From<Matrix> for Value {
+    fn from(m: Matrix) -> Self {
+        Matrix(m)
+    }
+}
+
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Number(n) => write!(f, "{n}"),
-            Undefined(u) => write!(f, "{u}"),
-        }
+        write!(f, "{}", FormattedValue(Format::Fraction, self))
     }
 }
 
@@ -127,6 +316,8 @@ impl Value {
     pub fn abs(self) -> Self {
         match self {
             Number(n) => Number(if n < 0 { -n } else { n }),
+            Quantity(q) => Quantity(q.abs()),
+            List(_) | Matrix(_) | Mod(_) | Poly(_) => Undefined(Undefined::TypeMismatch),
             Undefined(u) => Undefined(u),
         }
     }
@@ -143,29 +334,42 @@ impl Value {
         matches!(self, Self::Number(_))
     }
 
+    // Scalars are zero when multiplied by zero, and 1 when raised to the 0th.
+    pub fn is_scalar(&self) -> bool {
+        matches!(self, Self::Number(_) | Self::Poly(_) | Self::Undefined(_))
+    }
+
     pub fn is_undefined(&self) -> bool {
         matches!(self, Self::Undefined(_))
     }
 
     pub fn pow(self, other: &Self) -> Self {
         match (self, other) {
-            (Number(z), v) if z == 0 => match v {
-                Number(n) if *n == 0 => Number(Rational::ONE),
-                Number(n) if *n < 0 => Undefined(Undefined::Infinity),
-                Number(_) => Number(Rational::ZERO),
-                Undefined(u) => Undefined(u.clone()),
-            },
-            (Number(one), _) if one == 1 => Number(Rational::ONE),
-            (_, Number(z)) if *z == 0 => Number(Rational::ONE),
-            (Number(n), Number(m)) => {
-                if let Some(pow) = super::pow(n, m.clone()) {
-                    Number(pow)
+            (Number(z), Number(n)) if z == 0 => {
+                if *n == 0 {
+                    Number(Rational::ONE)
+                } else if *n < 0 {
+                    Undefined(Undefined::Infinity)
                 } else {
-                    Undefined(Undefined::Irrational)
+                    Number(Rational::ZERO)
                 }
             }
+            (left @ List(_), right) | (left, right @ List(_))
+                if !left.is_undefined() && !right.is_undefined() =>
+            {
+                list::elementwise(&left, right, Expression::Pow)
+            }
+            (Number(one), _) if one == 1 => Number(Rational::ONE),
+            (Mod(r), Number(n)) => r.pow(n).into(),
+            // The 0th power of a matrix is the identity matrix.
+            (Matrix(a), Number(n)) => a.pow(n).into(),
+            (_, Number(z)) if *z == 0 => Number(Rational::ONE),
+            (Number(n), Number(m)) => super::pow(n, m.clone()).into(),
+            (Poly(p), Number(n)) => p.pow(n).into(),
+            (Quantity(q), Number(n)) => q.pow(n).into(),
             (Undefined(u), _) => Undefined(u),
             (_, Undefined(u)) => Undefined(u.clone()),
+            _ => Undefined(Undefined::TypeMismatch),
         }
     }
 }
@@ -0,0 +1,111 @@
+use crate::expression::Expression;
+use crate::math::Value;
+use crate::program::{RcConstant, WeakFunction};
+use std::fmt;
+use std::rc::Rc;
+
+type StrictFn = dyn Fn(&[Value]) -> Value;
+type LazyFn = dyn Fn(&Arguments) -> Result<Value, Unevaluated>;
+type RewriteFn = dyn Fn(&Arguments) -> Result<Expression, Unevaluated>;
+
+// A function that's implemented in Rust. It may be a closure that captures some state.
+#[derive(Clone)]
+pub enum NativeFunction {
+    // Gets the values of all its arguments. If any of them is undefined, so is the result.
+    Strict(Rc<StrictFn>),
+    // Gets its arguments unevaluated, and only evaluates the ones it needs.
+    Lazy(Rc<LazyFn>),
+    // Like a lazy function, but replaces the call with a new expression, which is evaluated instead.
+    Rewrite(Rc<RewriteFn>),
+}
+
+impl NativeFunction {
+    pub fn strict(function: impl Fn(&[Value]) -> Value + 'static) -> Self {
+        Self::Strict(Rc::new(function))
+    }
+
+    pub fn lazy(function: impl Fn(&Arguments) -> Result<Value, Unevaluated> + 'static) -> Self {
+        Self::Lazy(Rc::new(function))
+    }
+
+    pub fn rewrite(
+        function: impl Fn(&Arguments) -> Result<Expression, Unevaluated> + 'static,
+    ) -> Self {
+        Self::Rewrite(Rc::new(function))
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Strict(_) => write!(f, "Strict(..)"),
+            Self::Lazy(_) => write!(f, "Lazy(..)"),
+            Self::Rewrite(_) => write!(f, "Rewrite(..)"),
+        }
+    }
+}
+
+// A function passed by name to a native function, e.g. f in integrate(f, 0, 1, 1/1000).
+#[derive(Clone, Debug)]
+pub enum FunctionRef {
+    User(usize, WeakFunction),
+    Native(usize, NativeFunction),
+}
+
+impl FunctionRef {
+    pub fn n_params(&self) -> usize {
+        match *self {
+            FunctionRef::User(n_params, _) | FunctionRef::Native(n_params, _) => n_params,
+        }
+    }
+
+    // Returns an expression that calls the function. It must get exactly n_params arguments.
+    pub fn call(&self, args: Vec<Expression>) -> Expression {
+        assert_eq!(args.len(), self.n_params());
+        match self {
+            FunctionRef::User(_, function) => Expression::Call(function.clone(), args),
+            FunctionRef::Native(_, function) => Expression::CallNative(function.clone(), args),
+        }
+    }
+}
+
+// Returned by a lazy native function when it needs a value that isn't evaluated yet:
+// either one of its arguments or an element of a list.
+// The value will be evaluated, and then the function will be called again.
+#[derive(Debug)]
+pub enum Unevaluated {
+    Argument(usize),
+    Element(RcConstant),
+}
+
+// Returns the value of an element of a list. Use it with `?`, so the element gets evaluated if needed.
+pub fn element_value(element: &RcConstant) -> Result<&Value, Unevaluated> {
+    element
+        .value_if_found()
+        .ok_or_else(|| Unevaluated::Element(Rc::clone(element)))
+}
+
+pub struct Arguments<'a> {
+    args: &'a [Expression],
+}
+
+impl<'a> Arguments<'a> {
+    pub fn new(args: &'a [Expression]) -> Self {
+        Self { args }
+    }
+
+    // Returns the value of the i'th argument. Use it with `?`, so the argument gets evaluated if needed.
+    pub fn get(&self, i: usize) -> Result<&'a Value, Unevaluated> {
+        self.args[i]
+            .value_if_found()
+            .ok_or(Unevaluated::Argument(i))
+    }
+
+    // Returns the i'th argument if it's a function.
+    pub fn function(&self, i: usize) -> Option<&'a FunctionRef> {
+        match &self.args[i] {
+            Expression::Function(function) => Some(function),
+            _ => None,
+        }
+    }
+}
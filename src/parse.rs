@@ -1,12 +1,19 @@
+use crate::math::markup::Markup;
+use crate::math::number_theory as nt;
+use crate::math::units;
+use malachite::num::conversion::traits::IsInteger;
 use malachite::rational_sequences::RationalSequence;
-use malachite::{Natural, Rational};
+use malachite::{Integer, Natural, Rational};
 use nom::branch::alt;
 use nom::bytes::complete::{tag, take_until};
 use nom::character::complete::{digit1, satisfy};
-use nom::combinator::{map, opt};
+use nom::combinator::{consumed, map, opt, recognize, verify};
 use nom::multi::{many0, many0_count, separated_list0, separated_list1};
 use nom::sequence::{delimited, pair, preceded, terminated};
-use nom::IResult;
+use nom::{IResult, Offset};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
 
 #[derive(Debug, thiserror::Error)]
 pub enum ParseError<'a> {
@@ -16,13 +23,25 @@ pub enum ParseError<'a> {
     Incomplete(&'a str),
 }
 
+impl ParseError<'_> {
+    // The line of the input where the error is, counting from 1.
+    pub fn line(&self, input: &str) -> usize {
+        let rest = match self {
+            ParseError::Nom(nom::Err::Error(err) | nom::Err::Failure(err)) => err.input,
+            ParseError::Nom(nom::Err::Incomplete(_)) => &input[input.len()..],
+            ParseError::Incomplete(rest) => rest,
+        };
+        line_number(input, rest)
+    }
+}
+
 impl<'a> From<nom::Err<nom::error::Error<&'a str>>> for ParseError<'a> {
     fn from(err: nom::Err<nom::error::Error<&'a str>>) -> Self {
         Self::Nom(err)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expression {
     Number(Rational),
     Identifier(String),
@@ -34,6 +53,11 @@ pub enum Expression {
     Div(Box<Expression>, Box<Expression>),
     Pow(Box<Expression>, Box<Expression>),
     Call(String, Vec<Expression>),
+    List(Vec<Expression>),
+    Matrix(Vec<Vec<Expression>>),
+    Index(Box<Expression>, Box<Expression>),
+    // A conversion to other units, e.g. 1 mile in km.
+    Convert(Box<Expression>, Box<Expression>),
 }
 
 pub enum Statement {
@@ -41,8 +65,264 @@ pub enum Statement {
     Evaluate(Expression),
 }
 
+// Where a statement was written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    pub line: usize, // Counting from 1.
+    pub text: String,
+    pub doc: Option<String>, // The comment right before the statement, without the asterisks.
+    pub file: Option<PathBuf>,
+}
+
 pub struct Code {
     pub statements: Vec<Statement>,
+    pub sources: Vec<Source>, // The source of each statement, or nothing if the code wasn't parsed from text.
+}
+
+// The last comment in the text between two statements, if it's on its own line
+// and there's no empty line between it and the next statement.
+fn doc_comment(gap: &str, at_start: bool) -> Option<String> {
+    let mut doc = None;
+    let mut rest = gap;
+    let mut on_own_line = at_start;
+    loop {
+        let after_whitespace = rest.trim_start();
+        let newlines = rest[..rest.len() - after_whitespace.len()]
+            .matches('\n')
+            .count();
+        if newlines > 1 {
+            doc = None;
+        }
+        on_own_line |= newlines > 0;
+        rest = after_whitespace;
+        if rest.is_empty() {
+            break;
+        }
+        let (after, ()) = comment(rest).ok()?;
+        let text = &rest[..rest.len() - after.len()];
+        doc = on_own_line.then_some(text);
+        on_own_line = false;
+        rest = after;
+    }
+    let lines = doc?
+        .trim_matches('*')
+        .lines()
+        .map(str::trim)
+        .collect::<Vec<_>>();
+    Some(lines.join("\n").trim().to_string())
+}
+
+// The line of the input that a slice of it starts on, counting from 1.
+fn line_number(input: &str, slice: &str) -> usize {
+    input[..input.offset(slice)].matches('\n').count() + 1
+}
+
+// Binding strengths of the operators, from weakest to strongest.
+const CONVERSION: u8 = 0;
+const SUM: u8 = 1;
+const PRODUCT: u8 = 2;
+const UNARY: u8 = 3;
+const POWER: u8 = 4;
+const ATOM: u8 = 5;
+
+impl Expression {
+    fn binding(&self) -> u8 {
+        match self {
+            Expression::Number(n) if *n < 0 && n.is_integer() => UNARY,
+            Expression::Number(n) if !n.is_integer() => PRODUCT,
+            Expression::Convert(..) => CONVERSION,
+            Expression::Add(..) | Expression::Sub(..) => SUM,
+            Expression::Mul(..) | Expression::Div(..) => PRODUCT,
+            Expression::Pos(_) | Expression::Neg(_) => UNARY,
+            Expression::Pow(..) => POWER,
+            _ => ATOM,
+        }
+    }
+
+    // Calls f with each identifier in the expression, and with the name of each function it calls.
+    pub fn for_each_name(&self, f: &mut impl FnMut(&String, bool)) {
+        match self {
+            Expression::Identifier(name) => f(name, false),
+            Expression::Number(_) => {}
+            Expression::Pos(expr) | Expression::Neg(expr) => expr.for_each_name(f),
+            Expression::Add(left, right)
+            | Expression::Sub(left, right)
+            | Expression::Mul(left, right)
+            | Expression::Div(left, right)
+            | Expression::Pow(left, right)
+            | Expression::Index(left, right)
+            | Expression::Convert(left, right) => {
+                left.for_each_name(f);
+                right.for_each_name(f);
+            }
+            Expression::Call(name, exprs) => {
+                f(name, true);
+                exprs.iter().for_each(|expr| expr.for_each_name(f));
+            }
+            Expression::List(exprs) => exprs.iter().for_each(|expr| expr.for_each_name(f)),
+            Expression::Matrix(rows) => {
+                rows.iter().flatten().for_each(|expr| expr.for_each_name(f))
+            }
+        }
+    }
+
+    // Writes the expression, in parentheses if it binds weaker than min_binding.
+    fn fmt_binding(&self, f: &mut fmt::Formatter<'_>, min_binding: u8) -> fmt::Result {
+        if self.binding() < min_binding {
+            write!(f, "({self})")
+        } else {
+            write!(f, "{self}")
+        }
+    }
+}
+
+fn fmt_separated(f: &mut fmt::Formatter<'_>, exprs: &[Expression]) -> fmt::Result {
+    for (i, expr) in exprs.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{expr}")?;
+    }
+    Ok(())
+}
+
+// Writes the expression as source code that parses back to the same expression.
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let binary = |f: &mut fmt::Formatter<'_>, left: &Self, op, right: &Self, binding| {
+            left.fmt_binding(f, binding)?;
+            write!(f, " {op} ")?;
+            right.fmt_binding(f, binding + 1)
+        };
+        match self {
+            Expression::Number(n) => write!(f, "{n}"),
+            Expression::Identifier(name) => write!(f, "{name}"),
+            Expression::Pos(expr) => {
+                write!(f, "+")?;
+                expr.fmt_binding(f, UNARY)
+            }
+            Expression::Neg(expr) => {
+                write!(f, "-")?;
+                expr.fmt_binding(f, UNARY)
+            }
+            Expression::Add(left, right) => binary(f, left, "+", right, SUM),
+            Expression::Sub(left, right) => binary(f, left, "-", right, SUM),
+            Expression::Mul(left, right) => {
+                left.fmt_binding(f, PRODUCT)?;
+                write!(f, "*")?;
+                right.fmt_binding(f, UNARY)
+            }
+            Expression::Div(left, right) => {
+                left.fmt_binding(f, PRODUCT)?;
+                write!(f, "/")?;
+                right.fmt_binding(f, UNARY)
+            }
+            Expression::Pow(left, right) => {
+                left.fmt_binding(f, ATOM)?;
+                write!(f, "^")?;
+                right.fmt_binding(f, UNARY)
+            }
+            Expression::Call(name, args) => {
+                write!(f, "{name}(")?;
+                fmt_separated(f, args)?;
+                write!(f, ")")
+            }
+            Expression::List(elements) => {
+                write!(f, "[")?;
+                fmt_separated(f, elements)?;
+                write!(f, "]")
+            }
+            Expression::Matrix(rows) => {
+                write!(f, "{{")?;
+                for (i, row) in rows.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    fmt_separated(f, row)?;
+                }
+                write!(f, "}}")
+            }
+            Expression::Index(list, index) => {
+                list.fmt_binding(f, ATOM)?;
+                write!(f, "[{index}]")
+            }
+            Expression::Convert(left, right) => binary(f, left, "in", right, SUM),
+        }
+    }
+}
+
+impl Expression {
+    // How strongly the typeset expression binds, where fractions are written as \frac and bind like powers.
+    fn typeset_binding(&self) -> u8 {
+        match self {
+            Expression::Number(n) if *n >= 0 && !n.is_integer() => POWER,
+            Expression::Div(..) => POWER,
+            _ => self.binding(),
+        }
+    }
+
+    fn typeset_binding_at_least(&self, markup: Markup, min_binding: u8) -> String {
+        if self.typeset_binding() < min_binding {
+            markup.enclosed("(", &self.typeset(markup), ")")
+        } else {
+            self.typeset(markup)
+        }
+    }
+
+    // Typesets the expression as LaTeX or MathML, e.g. \frac{x}{2}.
+    pub fn typeset(&self, markup: Markup) -> String {
+        let separated = |exprs: &[Self]| {
+            let exprs = exprs.iter().map(|e| e.typeset(markup)).collect::<Vec<_>>();
+            markup.separated(&exprs)
+        };
+        let binary = |left: &Self, op: String, right: &Self, binding| {
+            markup.row(&[
+                left.typeset_binding_at_least(markup, binding),
+                op,
+                right.typeset_binding_at_least(markup, binding + 1),
+            ])
+        };
+        match self {
+            Expression::Number(n) => markup.source_number(n),
+            Expression::Identifier(name) => markup.identifier(name),
+            Expression::Pos(expr) => markup.row(&[
+                markup.operator("+", "+").trim().to_string(),
+                expr.typeset_binding_at_least(markup, UNARY),
+            ]),
+            Expression::Neg(expr) => markup.negative(&expr.typeset_binding_at_least(markup, UNARY)),
+            Expression::Add(left, right) => binary(left, markup.operator("+", "+"), right, SUM),
+            Expression::Sub(left, right) => binary(left, markup.operator("-", "-"), right, SUM),
+            Expression::Mul(left, right) => markup.row(&[
+                left.typeset_binding_at_least(markup, PRODUCT),
+                markup.operator("⋅", "\\cdot"),
+                right.typeset_binding_at_least(markup, UNARY),
+            ]),
+            Expression::Div(left, right) => {
+                markup.fraction(&left.typeset(markup), &right.typeset(markup))
+            }
+            Expression::Pow(left, right) => markup.power(
+                &left.typeset_binding_at_least(markup, ATOM),
+                &right.typeset(markup),
+            ),
+            Expression::Call(name, args) => {
+                let args = args.iter().map(|e| e.typeset(markup)).collect::<Vec<_>>();
+                markup.call(name, &args)
+            }
+            Expression::List(elements) => markup.enclosed("[", &separated(elements), "]"),
+            Expression::Matrix(rows) => {
+                let rows = rows
+                    .iter()
+                    .map(|row| row.iter().map(|e| e.typeset(markup)).collect())
+                    .collect::<Vec<_>>();
+                markup.matrix(&rows)
+            }
+            Expression::Index(list, index) => markup.row(&[
+                list.typeset_binding_at_least(markup, ATOM),
+                markup.enclosed("[", &index.typeset(markup), "]"),
+            ]),
+            Expression::Convert(left, right) => binary(left, markup.text(" in "), right, SUM),
+        }
+    }
 }
 
 fn digits(input: &str) -> IResult<&str, Vec<u8>> {
@@ -115,24 +395,119 @@ fn ws0(input: &str, newline: bool) -> IResult<&str, ()> {
     }
 }
 
-fn parenthesized<'a, F, O>(input: &'a str, f: F) -> IResult<&'a str, O>
+// Parses f between an opening bracket from open_brackets and the matching closing bracket.
+fn enclosed<'a, F, O>(input: &'a str, open_brackets: &str, f: F) -> IResult<&'a str, O>
 where
     F: FnMut(&'a str) -> IResult<&'a str, O>,
 {
-    let (input, bracket) = alt((tag("("), tag("["), tag("{")))(input)?;
-    let (input, result) = preceded(pass_newline(ws0, true), f)(input)?;
-    let (input, _) = preceded(
-        pass_newline(ws0, true),
-        tag(match bracket {
-            "(" => ")",
-            "[" => "]",
-            "{" => "}",
-            _ => unreachable!(),
-        }),
-    )(input)?;
+    let Some(bracket) = open_brackets.chars().find(|&b| input.starts_with(b)) else {
+        return Err(nom::Err::Error(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::Tag,
+        )));
+    };
+    let close = match bracket {
+        '(' => ")",
+        '[' => "]",
+        '{' => "}",
+        _ => unreachable!(),
+    };
+    let (input, result) = preceded(pass_newline(ws0, true), f)(&input[1..])?;
+    let (input, _) = preceded(pass_newline(ws0, true), tag(close))(input)?;
     Ok((input, result))
 }
 
+fn parenthesized<'a, F, O>(input: &'a str, f: F) -> IResult<&'a str, O>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, O>,
+{
+    enclosed(input, "([{", f)
+}
+
+fn list(input: &str) -> IResult<&str, Vec<Expression>> {
+    enclosed(
+        input,
+        "[",
+        terminated(
+            separated_list0(
+                preceded(pass_newline(ws0, true), tag(",")),
+                preceded(pass_newline(ws0, true), pass_newline(expr, true)),
+            ),
+            opt(preceded(pass_newline(ws0, true), tag(","))),
+        ),
+    )
+}
+
+fn integer(input: &str) -> IResult<&str, Integer> {
+    map(digit1, |digits: &str| Integer::from_str(digits).unwrap())(input)
+}
+
+// Parses a continued fraction, e.g. [1; 2, 2, 2], which is 1 + 1/(2 + 1/(2 + 1/2)).
+// The first term is an integer, and the others are positive integers.
+fn continued_fraction(input: &str) -> IResult<&str, Rational> {
+    map(
+        |i| {
+            enclosed(
+                i,
+                "[",
+                pair(
+                    terminated(
+                        map(pair(opt(tag("-")), integer), |(sign, n)| {
+                            if sign.is_some() {
+                                -n
+                            } else {
+                                n
+                            }
+                        }),
+                        preceded(pass_newline(ws0, true), tag(";")),
+                    ),
+                    terminated(
+                        separated_list0(
+                            preceded(pass_newline(ws0, true), tag(",")),
+                            preceded(pass_newline(ws0, true), verify(integer, |n| *n > 0)),
+                        ),
+                        opt(preceded(pass_newline(ws0, true), tag(","))),
+                    ),
+                ),
+            )
+        },
+        |(first, mut rest)| {
+            rest.insert(0, first);
+            nt::from_continued_fraction(&rest)
+        },
+    )(input)
+}
+
+// Parses a matrix, e.g. {1, 2; 3, 4}.
+// A single expression in curly brackets, e.g. {1}, is just that expression.
+fn matrix(input: &str) -> IResult<&str, Expression> {
+    map(
+        |i| {
+            enclosed(
+                i,
+                "{",
+                separated_list1(
+                    preceded(pass_newline(ws0, true), tag(";")),
+                    preceded(
+                        pass_newline(ws0, true),
+                        separated_list1(
+                            preceded(pass_newline(ws0, true), tag(",")),
+                            preceded(pass_newline(ws0, true), pass_newline(expr, true)),
+                        ),
+                    ),
+                ),
+            )
+        },
+        |mut rows| {
+            if rows.len() == 1 && rows[0].len() == 1 {
+                rows.pop().unwrap().pop().unwrap()
+            } else {
+                Expression::Matrix(rows)
+            }
+        },
+    )(input)
+}
+
 fn pass_newline<F, T>(mut f: F, newline: bool) -> impl FnMut(&str) -> IResult<&str, T>
 where
     F: FnMut(&str, bool) -> IResult<&str, T>,
@@ -141,7 +516,7 @@ where
 }
 
 fn expr1(input: &str, newline: bool) -> IResult<&str, Expression> {
-    alt((
+    let (mut input, mut expr) = alt((
         map(
             pair(
                 identifier,
@@ -166,27 +541,84 @@ fn expr1(input: &str, newline: bool) -> IResult<&str, Expression> {
                 }
             },
         ),
+        map(result_reference, Expression::Identifier),
         map(number, Expression::Number),
-        |i| parenthesized(i, pass_newline(expr, true)),
-    ))(input)
+        map(continued_fraction, Expression::Number),
+        map(list, Expression::List),
+        matrix,
+        |i| enclosed(i, "(", pass_newline(self::expr, true)),
+    ))(input)?;
+    while let Ok((rest, index)) = preceded(pass_newline(ws0, newline), |i| {
+        enclosed(i, "[", pass_newline(self::expr, true))
+    })(input)
+    {
+        expr = Expression::Index(Box::new(expr), Box::new(index));
+        input = rest;
+    }
+    Ok((input, expr))
 }
 
-fn expr2(input: &str, newline: bool) -> IResult<&str, Expression> {
+// A numbered result of an earlier computation, e.g. $3.
+fn result_reference(input: &str) -> IResult<&str, String> {
+    map(recognize(pair(tag("$"), digit1)), str::to_string)(input)
+}
+
+// The keyword of the unit conversion operator, which isn't part of a longer identifier.
+fn keyword_in(input: &str) -> IResult<&str, String> {
+    verify(identifier, |ident: &str| ident == "in")(input)
+}
+
+// A number followed by a unit, e.g. 3 m or 2 m^2, which is the product of the number and the unit.
+// They must be separated by whitespace. Only built-in units are allowed, so 3 x is still an error.
+fn quantity(input: &str, newline: bool) -> IResult<&str, Expression> {
     map(
         pair(
-            pass_newline(expr1, newline),
-            opt(preceded(
-                preceded(pass_newline(ws0, newline), tag("^")),
-                preceded(pass_newline(ws0, newline), pass_newline(expr3, newline)),
-            )),
+            terminated(
+                number,
+                verify(recognize(pass_newline(ws0, newline)), |ws: &str| {
+                    !ws.is_empty()
+                }),
+            ),
+            pair(
+                verify(identifier, |name: &str| {
+                    units().iter().any(|unit| unit.name == name)
+                }),
+                opt(preceded(
+                    preceded(pass_newline(ws0, newline), tag("^")),
+                    preceded(pass_newline(ws0, newline), pass_newline(expr3, newline)),
+                )),
+            ),
         ),
-        |(left, right)| match right {
-            Some(right) => Expression::Pow(Box::new(left), Box::new(right)),
-            None => left,
+        |(n, (unit, exponent))| {
+            let unit = Expression::Identifier(unit);
+            let unit = match exponent {
+                Some(exponent) => Expression::Pow(Box::new(unit), Box::new(exponent)),
+                None => unit,
+            };
+            Expression::Mul(Box::new(Expression::Number(n)), Box::new(unit))
         },
     )(input)
 }
 
+fn expr2(input: &str, newline: bool) -> IResult<&str, Expression> {
+    alt((
+        pass_newline(quantity, newline),
+        map(
+            pair(
+                pass_newline(expr1, newline),
+                opt(preceded(
+                    preceded(pass_newline(ws0, newline), tag("^")),
+                    preceded(pass_newline(ws0, newline), pass_newline(expr3, newline)),
+                )),
+            ),
+            |(left, right)| match right {
+                Some(right) => Expression::Pow(Box::new(left), Box::new(right)),
+                None => left,
+            },
+        ),
+    ))(input)
+}
+
 fn expr3(input: &str, newline: bool) -> IResult<&str, Expression> {
     map(
         pair(
@@ -253,8 +685,21 @@ fn expr5(input: &str, newline: bool) -> IResult<&str, Expression> {
     )(input)
 }
 
+// The lowest precedence operator is unit conversion, e.g. 60 mph in km/h.
 fn expr(input: &str, newline: bool) -> IResult<&str, Expression> {
-    expr5(input, newline)
+    map(
+        pair(
+            pass_newline(expr5, newline),
+            opt(preceded(
+                preceded(pass_newline(ws0, newline), keyword_in),
+                preceded(pass_newline(ws0, newline), pass_newline(expr5, newline)),
+            )),
+        ),
+        |(left, right)| match right {
+            Some(right) => Expression::Convert(Box::new(left), Box::new(right)),
+            None => left,
+        },
+    )(input)
 }
 
 fn statement(input: &str) -> IResult<&str, Statement> {
@@ -280,14 +725,53 @@ fn program(input: &str) -> IResult<&str, Code> {
             pass_newline(ws0, true),
             separated_list0(
                 delimited(pass_newline(ws0, false), tag("\n"), pass_newline(ws0, true)),
-                statement,
+                consumed(statement),
             ),
             pass_newline(ws0, true),
         ),
-        |statements| Code { statements },
+        |statements| {
+            let mut previous_end = 0;
+            let mut sources = vec![];
+            for &(text, _) in &statements {
+                let start = input.offset(text);
+                sources.push(Source {
+                    line: line_number(input, text),
+                    text: text.trim().to_string(),
+                    doc: doc_comment(&input[previous_end..start], previous_end == 0),
+                    file: None,
+                });
+                previous_end = start + text.len();
+            }
+            Code {
+                statements: statements.into_iter().map(|(_, stmt)| stmt).collect(),
+                sources,
+            }
+        },
     )(input)
 }
 
+// Whether the input ends inside brackets or a comment, so the statement continues on the next line.
+pub fn is_incomplete(input: &str) -> bool {
+    let mut depth = 0;
+    let mut rest = input;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("**") {
+            match comment(rest) {
+                Ok((after, ())) => rest = after,
+                Err(_) => return true,
+            }
+            continue;
+        }
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            _ => {}
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    depth > 0
+}
+
 pub fn parse(input: &str) -> Result<Code, ParseError<'_>> {
     let (rest, prog) = program(input)?;
     if !rest.is_empty() {
@@ -467,6 +951,108 @@ mod test {
         assert_eq!(rest, "(x, y]");
     }
 
+    #[test]
+    fn list_test() {
+        let (rest, expr) = expr1("[1, x,\n]y", false).unwrap();
+        assert!(matches!(
+            expr,
+            Expression::List(elements)
+            if matches!(
+                &elements[..],
+                [Expression::Number(n), Expression::Identifier(x)]
+                if *n == frac(1, 1) && x == "x",
+            ),
+        ));
+        assert_eq!(rest, "y");
+
+        let (rest, expr) = expr1("[]", false).unwrap();
+        assert!(matches!(expr, Expression::List(elements) if elements.is_empty()));
+        assert_eq!(rest, "");
+
+        let (rest, expr) = expr1("[x] [0]", false).unwrap();
+        assert!(matches!(
+            expr,
+            Expression::Index(list, index)
+            if matches!(&*list, Expression::List(elements) if elements.len() == 1)
+            && matches!(&*index, Expression::Number(n) if *n == frac(0, 1)),
+        ));
+        assert_eq!(rest, "");
+
+        let (rest, expr) = expr1("f(x)[1][2]", false).unwrap();
+        assert!(matches!(
+            expr,
+            Expression::Index(inner, two)
+            if matches!(
+                &*inner,
+                Expression::Index(call, one)
+                if matches!(&**call, Expression::Call(f, _) if f == "f")
+                && matches!(&**one, Expression::Number(n) if *n == frac(1, 1)),
+            )
+            && matches!(&*two, Expression::Number(n) if *n == frac(2, 1)),
+        ));
+        assert_eq!(rest, "");
+
+        let (rest, expr) = expr1("{1}", false).unwrap();
+        assert!(matches!(expr, Expression::Number(n) if n == frac(1, 1)));
+        assert_eq!(rest, "");
+
+        assert!(expr1("[1, 2)", false).is_err());
+    }
+
+    #[test]
+    fn continued_fraction_test() {
+        let (rest, n) = continued_fraction("[1; 2, 2, 2]x").unwrap();
+        assert_eq!(n, frac(17, 12));
+        assert_eq!(rest, "x");
+
+        let (rest, n) = continued_fraction("[-4;\n 2,]").unwrap();
+        assert_eq!(n, -frac(7, 2));
+        assert_eq!(rest, "");
+
+        let (rest, n) = continued_fraction("[3;]").unwrap();
+        assert_eq!(n, frac(3, 1));
+        assert_eq!(rest, "");
+
+        assert!(continued_fraction("[1; 0]").is_err());
+        assert!(continued_fraction("[1; 2.5]").is_err());
+        assert!(continued_fraction("[1, 2]").is_err());
+
+        let (rest, e) = expr1("[1, 2]", false).unwrap();
+        assert!(matches!(e, Expression::List(elements) if elements.len() == 2));
+        assert_eq!(rest, "");
+    }
+
+    #[test]
+    fn matrix_test() {
+        let (rest, expr) = expr1("{1, 2;\n 3, x}y", false).unwrap();
+        assert!(matches!(
+            expr,
+            Expression::Matrix(rows)
+            if matches!(
+                &rows[..],
+                [first, second]
+                if matches!(
+                    &first[..],
+                    [Expression::Number(a), Expression::Number(b)]
+                    if *a == frac(1, 1) && *b == frac(2, 1),
+                )
+                && matches!(
+                    &second[..],
+                    [Expression::Number(c), Expression::Identifier(x)]
+                    if *c == frac(3, 1) && x == "x",
+                ),
+            ),
+        ));
+        assert_eq!(rest, "y");
+
+        let (rest, expr) = expr1("{1; 2}", false).unwrap();
+        assert!(matches!(expr, Expression::Matrix(rows) if rows.len() == 2 && rows[0].len() == 1));
+        assert_eq!(rest, "");
+
+        assert!(expr1("{}", false).is_err());
+        assert!(expr1("{1, 2;}", false).is_err());
+    }
+
     #[test]
     fn pow_unary_expression_test() {
         let (rest, expr) = expr2("1^2x", false).unwrap();
@@ -596,6 +1182,30 @@ mod test {
         assert_eq!(rest, "xyz");
     }
 
+    #[test]
+    fn quantity_test() {
+        let (rest, e) = expr("9.81 m/s^2", false).unwrap();
+        assert_eq!(e.to_string(), "981/100*m/s^2");
+        assert_eq!(rest, "");
+
+        let (rest, e) = expr("2 m^2 in inch^2", false).unwrap();
+        assert_eq!(e.to_string(), "2*m^2 in inch^2");
+        assert_eq!(rest, "");
+
+        let (rest, e) = expr("1 inch in\n cm", false).unwrap();
+        assert_eq!(e.to_string(), "1*inch");
+        assert_eq!(rest, " in\n cm");
+
+        let (rest, e) = expr("3 index", false).unwrap();
+        assert_eq!(e.to_string(), "3");
+        assert_eq!(rest, " index");
+        assert!(parse("3 index").is_err());
+
+        let (rest, e) = expr("3 in", false).unwrap();
+        assert_eq!(e.to_string(), "3");
+        assert_eq!(rest, " in");
+    }
+
     #[test]
     fn statement_test() {
         let (rest, stmt) = statement("x1 = y1 = z1#").unwrap();
@@ -637,7 +1247,7 @@ mod test {
         .unwrap();
         assert!(matches!(
             prog,
-            Code { statements }
+            Code { statements, .. }
             if matches!(
                 &statements[..],
                 [Statement::Assign(assign), Statement::Evaluate(eval)]
@@ -666,4 +1276,97 @@ mod test {
             if matches!(&*pi, Expression::Identifier(s) if s == "_π'")),);
         assert_eq!(rest, "!");
     }
+
+    #[test]
+    fn display_test() {
+        for code in [
+            "1 + 2*3 - 4/5",
+            "(1 + 2)*3 - 4/(5 - 6)",
+            "-x^2 + (-x)^2 + x^-2",
+            "2^3^4 + (2^3)^4",
+            "1/2*x^(1/2)",
+            "a - (b - c) - d + -e",
+            "f(x, [1, 2], {1, 2; 3, 4})[0]",
+            "(x + 1)[2]",
+            "3*m + 2*km in ft",
+            "(1 in m) + 1",
+        ] {
+            let (rest, e) = expr(code, false).unwrap();
+            assert_eq!(rest, "");
+            assert_eq!(e.to_string(), code);
+        }
+    }
+
+    #[test]
+    fn typeset_test() {
+        for (code, latex) in [
+            ("(x^2 - 1)/2", "\\frac{x^{2} - 1}{2}"),
+            ("(a/b)^2 + 0.5", "\\left(\\frac{a}{b}\\right)^{2} + 0.5"),
+            (
+                "-(x + 1)*fact(n)",
+                "-\\left(x + 1\\right) \\cdot \\mathrm{fact}\\left(n\\right)",
+            ),
+            ("x^(1/3)", "x^{\\frac{1}{3}}"),
+            ("[1, 2][0]", "\\left[1, 2\\right]\\left[0\\right]"),
+        ] {
+            let (rest, e) = expr(code, false).unwrap();
+            assert_eq!(rest, "");
+            assert_eq!(e.typeset(Markup::Latex), latex);
+        }
+        let (_, e) = expr("x/2", false).unwrap();
+        assert_eq!(
+            e.typeset(Markup::MathMl),
+            "<mfrac><mi>x</mi><mn>2</mn></mfrac>"
+        );
+    }
+
+    #[test]
+    fn result_reference_test() {
+        let (rest, e) = expr("$12 + ans", false).unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(e.to_string(), "$12 + ans");
+        assert!(expr("$x", false).is_err());
+    }
+
+    #[test]
+    fn doc_comment_test() {
+        let code = program(
+            "\
+            ** Not documentation. **\n\
+            \n\
+            x = 1 ** Not documentation either. **\n\
+            y = 2\n\
+            *** The answer,\n    to everything. ***\n\
+            z = 42\n\
+            ** Too far away. **\n\
+            \n\
+            f(x) = x\
+            ",
+        )
+        .unwrap()
+        .1;
+        let docs = code
+            .sources
+            .iter()
+            .map(|s| s.doc.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            docs,
+            [None, None, Some("The answer,\nto everything."), None]
+        );
+        let lines = code.sources.iter().map(|s| s.line).collect::<Vec<_>>();
+        assert_eq!(lines, [3, 4, 7, 10]);
+        assert_eq!(doc_comment("** Doc. **\n", true).as_deref(), Some("Doc."));
+    }
+
+    #[test]
+    fn is_incomplete_test() {
+        assert!(!is_incomplete("f(x) = [1, 2]\n"));
+        assert!(is_incomplete("f(x) = if(\n"));
+        assert!(is_incomplete("{1, 2;\n[3"));
+        assert!(is_incomplete("1 + 1 *** a comment ** \n"));
+        assert!(!is_incomplete("1 + 1 *** a comment ***"));
+        assert!(!is_incomplete("** ( **\n"));
+        assert!(!is_incomplete("1)\n"));
+    }
 }
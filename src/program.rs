@@ -1,12 +1,17 @@
+use crate::builtin;
 use crate::ctrlc_handler::CtrlCError;
 use crate::environment::{Environment, EvaluationEnvironemnt};
 use crate::expression::{Expression, SimplifyResult};
+use crate::math::format::Format;
 use crate::math::Value;
+use crate::native::NativeFunction;
+use crate::parse as p;
 use either::Either;
 use std::cell::OnceCell;
-use std::cell::RefCell;
+use std::cell::{Ref, RefCell};
 use std::collections::HashMap;
 use std::io::{self};
+use std::path::{Path, PathBuf};
 use std::rc::{Rc, Weak};
 
 #[derive(Debug, thiserror::Error)]
@@ -46,6 +51,11 @@ impl LazyExpression {
         self.value.get()
     }
 
+    // The expression, unless it's being simplified right now.
+    pub fn try_expression(&self) -> Option<Ref<'_, Expression>> {
+        self.expression.try_borrow().ok()
+    }
+
     fn simplify(&self, env: &mut EvaluationEnvironemnt) -> Result<Option<&Value>, CtrlCError> {
         if let Some(n) = self.value.get() {
             return Ok(Some(n));
@@ -79,7 +89,8 @@ impl LazyExpression {
 }
 
 pub struct Function {
-    n_params: usize,
+    params: Vec<String>,
+    source: p::Expression, // The code as it was written, before compilation.
     code: Expression,
 }
 
@@ -88,12 +99,28 @@ impl Function {
         Rc::new(OnceCell::new())
     }
 
-    pub fn new(n_params: usize, code: Expression) -> Self {
-        Self { n_params, code }
+    pub fn new(params: Vec<String>, source: p::Expression, code: Expression) -> Self {
+        Self {
+            params,
+            source,
+            code,
+        }
+    }
+
+    pub fn params(&self) -> &[String] {
+        &self.params
+    }
+
+    pub fn source(&self) -> &p::Expression {
+        &self.source
+    }
+
+    pub fn code(&self) -> &Expression {
+        &self.code
     }
 
     pub fn call(&self, args: &[RcConstant]) -> Expression {
-        assert_eq!(args.len(), self.n_params);
+        assert_eq!(args.len(), self.params.len());
         let mut code = self.code.clone();
         code.substitute_args(args);
         code
@@ -131,6 +158,12 @@ impl WeakConstant {
             .cloned()
     }
 
+    pub fn upgrade(&self) -> RcConstant {
+        self.data
+            .upgrade()
+            .expect("constant reference was dropped too early")
+    }
+
     fn simplify(self, env: &mut EvaluationEnvironemnt) -> Result<(), CtrlCError> {
         let mut to_simplify = self;
         loop {
@@ -180,6 +213,12 @@ impl WeakFunction {
             .unwrap_or_else(|_| panic!("function reference was initialized twice"));
     }
 
+    pub fn upgrade(&self) -> RcFunction {
+        self.data
+            .upgrade()
+            .expect("function reference was dropped too early")
+    }
+
     pub fn call(&self, args: &[RcConstant]) -> Expression {
         self.data
             .upgrade()
@@ -198,22 +237,86 @@ impl From<&RcFunction> for WeakFunction {
     }
 }
 
+// Evaluates the elements of a list (and of any lists nested in it), so it can be displayed.
+fn evaluate_elements(value: &Value, env: &mut EvaluationEnvironemnt) -> Result<(), CtrlCError> {
+    if let Value::List(list) = value {
+        for element in list.iter() {
+            let element = element.evaluate(env)?;
+            evaluate_elements(element, env)?;
+        }
+    }
+    Ok(())
+}
+
+// What a constant or function was defined as, e.g. for a = b = 2 it's 2 for both.
+#[derive(Debug)]
+pub struct DefinitionInfo {
+    pub names: Vec<String>, // All the names that the statement assigned to.
+    pub value: p::Expression,
+    pub source: Option<p::Source>,
+}
+
 #[derive(Clone)]
 enum Definition {
     Constant {
         constant: RcConstant,
+        info: Option<Rc<DefinitionInfo>>, // None for results.
     },
     Function {
         n_params: usize,
         function: RcFunction,
+        info: Rc<DefinitionInfo>,
+    },
+    Native {
+        n_params: usize,
+        function: NativeFunction,
     },
+    BuiltinConstant {
+        value: Value,
+    },
+}
+
+impl Definition {
+    // Whether other definitions can hold weak references to it.
+    fn is_referable(&self) -> bool {
+        matches!(
+            self,
+            Definition::Constant { .. } | Definition::Function { .. }
+        )
+    }
+
+    fn is_same(&self, other: &Definition) -> bool {
+        match (self, other) {
+            (
+                Definition::Constant { constant: a, .. },
+                Definition::Constant { constant: b, .. },
+            ) => Rc::ptr_eq(a, b),
+            (
+                Definition::Function { function: a, .. },
+                Definition::Function { function: b, .. },
+            ) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Evaluation {
+    expression: Expression,
+    format: Option<Format>, // The format to output it in, if not the default.
+    source: Option<p::Source>,
 }
 
 #[derive(Clone)]
 pub struct Program {
     old_definitions: Vec<Definition>, // Makes sure old definitions don't get deleted when they are still reachable.
     definitions: HashMap<String, Definition>,
-    to_evaluate: Vec<Expression>,
+    to_evaluate: Vec<Evaluation>,
+    n_results: usize, // The number of the last result, which can be referred to as $n_results.
+    transcript: Vec<(usize, p::Source)>, // The results of evaluating code that was typed in or loaded.
+    version: usize, // Changes whenever a definition is added, replaced or removed.
+    files: Vec<PathBuf>, // The files that were loaded, in the order they were first loaded.
+    live: bool,     // Whether replacing a definition recompiles the definitions that depend on it.
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -222,23 +325,82 @@ pub struct DefinitionDidntExist;
 
 impl Program {
     pub fn new() -> Self {
-        Self {
+        let mut program = Self {
             old_definitions: vec![],
             definitions: HashMap::new(),
             to_evaluate: vec![],
-        }
+            n_results: 0,
+            transcript: vec![],
+            version: 0,
+            files: vec![],
+            live: false,
+        };
+        builtin::define_builtins(&mut program);
+        program
     }
 
     pub fn get_constant_or_function(&self, name: &str) -> Option<Either<&RcConstant, &RcFunction>> {
         match self.definitions.get(name)? {
-            Definition::Constant { constant } => Some(Either::Left(constant)),
+            Definition::Constant { constant, .. } => Some(Either::Left(constant)),
             Definition::Function { function, .. } => Some(Either::Right(function)),
+            Definition::Native { .. } | Definition::BuiltinConstant { .. } => None,
         }
     }
 
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.definitions.keys().map(String::as_str)
+    }
+
     pub fn get_constant(&self, name: &str) -> Option<&RcConstant> {
         match self.definitions.get(name)? {
-            Definition::Constant { constant } => Some(constant),
+            Definition::Constant { constant, .. } => Some(constant),
+            _ => None,
+        }
+    }
+
+    // What the name of a definition is assigned to, e.g. f(x, y).
+    pub fn left_side(&self, name: &str) -> p::Expression {
+        match self.get_function(name).and_then(|function| function.get()) {
+            Some(function) => {
+                let params = function.params().iter().cloned();
+                p::Expression::Call(
+                    name.to_string(),
+                    params.map(p::Expression::Identifier).collect(),
+                )
+            }
+            None => p::Expression::Identifier(name.to_string()),
+        }
+    }
+
+    // The sorted names of the definitions that a user definition refers to.
+    pub fn dependencies(&self, name: &str) -> Vec<String> {
+        let Some(info) = self.get_info(name) else {
+            return vec![];
+        };
+        let params = match self.get_function(name).and_then(|function| function.get()) {
+            Some(function) => function.params(),
+            None => &[],
+        };
+        let mut dependencies = vec![];
+        info.value.for_each_name(&mut |dependency, _| {
+            if !params.contains(dependency) && self.definitions.contains_key(dependency) {
+                dependencies.push(dependency.clone());
+            }
+        });
+        dependencies.sort();
+        dependencies.dedup();
+        dependencies
+    }
+
+    pub fn transcript(&self) -> &[(usize, p::Source)] {
+        &self.transcript
+    }
+
+    // How a constant or function was defined by the user.
+    pub fn get_info(&self, name: &str) -> Option<&DefinitionInfo> {
+        match self.definitions.get(name)? {
+            Definition::Constant { info, .. } => info.as_deref(),
+            Definition::Function { info, .. } => Some(info),
             _ => None,
         }
     }
@@ -258,33 +420,92 @@ impl Program {
         Some(WeakFunction::from(self.get_function(name)?))
     }
 
+    pub fn get_native(&self, name: &str) -> Option<NativeFunction> {
+        match self.definitions.get(name)? {
+            Definition::Native { function, .. } => Some(function.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn get_builtin_constant(&self, name: &str) -> Option<&Value> {
+        match self.definitions.get(name)? {
+            Definition::BuiltinConstant { value } => Some(value),
+            _ => None,
+        }
+    }
+
     pub fn get_n_params(&self, function: &str) -> Option<usize> {
         match self.definitions.get(function)? {
-            &Definition::Function { n_params, .. } => Some(n_params),
+            &Definition::Function { n_params, .. } | &Definition::Native { n_params, .. } => {
+                Some(n_params)
+            }
             _ => None,
         }
     }
 
-    pub fn define_constant(&mut self, name: String) {
+    pub fn define_constant(&mut self, name: String, info: Rc<DefinitionInfo>) {
         let old_def = self.definitions.insert(
             name,
             Definition::Constant {
                 constant: LazyExpression::new_uninit(),
+                info: Some(info),
             },
         );
+        self.version += 1;
         if let Some(old_def) = old_def {
             self.old_definitions.push(old_def);
         }
     }
 
-    pub fn define_function(&mut self, name: String, n_params: usize) {
+    // Defines a constant whose value has already been evaluated.
+    // A result that's replaced, like the old ans, is still defined as its numbered result,
+    // so it doesn't need to be kept.
+    fn define_result(&mut self, name: String, constant: RcConstant) {
+        let old_def = self.definitions.insert(
+            name,
+            Definition::Constant {
+                constant,
+                info: None,
+            },
+        );
+        match old_def {
+            None | Some(Definition::Constant { info: None, .. }) => {}
+            Some(old_def) => self.old_definitions.push(old_def),
+        }
+    }
+
+    pub fn define_function(&mut self, name: String, n_params: usize, info: Rc<DefinitionInfo>) {
         let old_def = self.definitions.insert(
             name,
             Definition::Function {
                 function: Function::new_uninit(),
                 n_params,
+                info,
             },
         );
+        self.version += 1;
+        if let Some(old_def) = old_def {
+            self.old_definitions.push(old_def);
+        }
+    }
+
+    // Defines a function that's implemented in Rust. Like any other definition,
+    // it can be replaced by a user definition with the same name.
+    pub fn define_native(&mut self, name: &str, n_params: usize, function: NativeFunction) {
+        let old_def = self
+            .definitions
+            .insert(name.to_string(), Definition::Native { n_params, function });
+        self.version += 1;
+        if let Some(old_def) = old_def {
+            self.old_definitions.push(old_def);
+        }
+    }
+
+    pub fn define_builtin_constant(&mut self, name: &str, value: Value) {
+        let old_def = self
+            .definitions
+            .insert(name.to_string(), Definition::BuiltinConstant { value });
+        self.version += 1;
         if let Some(old_def) = old_def {
             self.old_definitions.push(old_def);
         }
@@ -293,6 +514,7 @@ impl Program {
     pub fn undefine(&mut self, name: &str) -> Result<(), DefinitionDidntExist> {
         match self.definitions.remove(name) {
             Some(old_def) => {
+                self.version += 1;
                 self.old_definitions.push(old_def);
                 Ok(())
             }
@@ -300,16 +522,150 @@ impl Program {
         }
     }
 
-    pub fn evaluate_later(&mut self, expr: Expression) {
-        self.to_evaluate.push(expr);
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    pub fn add_file(&mut self, file: &Path) {
+        if !self.files.iter().any(|f| f == file) {
+            self.files.push(file.to_path_buf());
+        }
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.live
+    }
+
+    pub fn set_live(&mut self, live: bool) {
+        self.live = live;
+    }
+
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    // Goes back to the definitions of an earlier state of the program. The results are kept,
+    // and so are the values of constants, since they are shared with the earlier state.
+    pub fn restore(&mut self, state: &Program) {
+        // The state is an earlier copy of this program, so its old definitions are already kept.
+        let definitions = std::mem::replace(&mut self.definitions, state.definitions.clone());
+        for (name, definition) in definitions {
+            if let Definition::Constant { info: None, .. } = definition {
+                self.definitions.insert(name, definition);
+            } else if definition.is_referable()
+                && !self.definitions.values().any(|d| d.is_same(&definition))
+                && !self.old_definitions.iter().any(|d| d.is_same(&definition))
+            {
+                self.old_definitions.push(definition);
+            }
+        }
+        self.files = state.files.clone();
+        self.version += 1;
+    }
+
+    pub fn evaluate_later(
+        &mut self,
+        expression: Expression,
+        format: Option<Format>,
+        source: Option<p::Source>,
+    ) {
+        self.to_evaluate.push(Evaluation {
+            expression,
+            format,
+            source,
+        });
     }
 
     pub fn run(&mut self, env: &mut Environment<'_>) -> Result<(), ProgramError> {
-        for expr in self.to_evaluate.drain(..) {
-            let expr = LazyExpression::new(expr);
-            let value = expr.evaluate(&mut env.evaluation_environment)?;
-            env.output_value(value)?;
+        for evaluation in std::mem::take(&mut self.to_evaluate) {
+            let constant = Rc::new(LazyExpression::new(evaluation.expression));
+            let value = constant.evaluate(&mut env.evaluation_environment)?;
+            evaluate_elements(value, &mut env.evaluation_environment)?;
+            self.n_results += 1;
+            let format = evaluation.format.unwrap_or(env.io_options.output_format);
+            env.output_result(value, format, evaluation.source.as_ref(), self.n_results)?;
+            if let Some(source) = evaluation.source {
+                self.transcript.push((self.n_results, source));
+            }
+            // The result keeps its value, so referring to it doesn't evaluate it again.
+            for name in [
+                "ans".to_string(),
+                "_".to_string(),
+                format!("${}", self.n_results),
+            ] {
+                self.define_result(name, constant.clone());
+            }
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{compile, parse};
+
+    #[test]
+    fn restore_test() {
+        let mut program = Program::new();
+        let n_old_definitions = program.old_definitions.len();
+        for i in 1..=50 {
+            let backup = program.clone();
+            compile::compile_into(parse::parse("x = 1").unwrap(), &mut program).unwrap();
+            program.restore(&backup);
+            // Only the replaced x is kept each time.
+            assert_eq!(program.old_definitions.len(), n_old_definitions + i);
+        }
+        assert!(program.get_constant("x").is_none());
+    }
+
+    #[test]
+    fn results_test() {
+        let mut program = Program::new();
+        let mut output: Vec<u8> = vec![];
+        let mut env = Environment::default();
+        env.io_options.output = Box::new(&mut output);
+        let n_old_definitions = program.old_definitions.len();
+        for i in 1..=50 {
+            let code = parse::parse(&format!("{i}")).unwrap();
+            compile::compile_into(code, &mut program).unwrap();
+            program.run(&mut env).unwrap();
+            if i == 1 {
+                // f refers to the first ans, which is replaced by the next results.
+                let code = parse::parse("f(x) = x + ans").unwrap();
+                compile::compile_into(code, &mut program).unwrap();
+            }
+        }
+        // The old values of ans and _ are only kept as numbered results.
+        assert_eq!(program.old_definitions.len(), n_old_definitions);
+        assert!(program.get_constant("$1").is_some());
+        compile::compile_into(parse::parse("f(1)").unwrap(), &mut program).unwrap();
+        program.run(&mut env).unwrap();
+        drop(env);
+        assert!(String::from_utf8(output).unwrap().ends_with("2\n"));
+    }
+    #[test]
+    fn native_closure_test() {
+        let mut program = Program::new();
+        let mut output: Vec<u8> = vec![];
+        let mut env = Environment::default();
+        env.io_options.output = Box::new(&mut output);
+        // The closure captures how many times it was called.
+        let calls = Rc::new(std::cell::Cell::new(0));
+        let counter = Rc::clone(&calls);
+        program.define_native(
+            "countedSquare",
+            1,
+            NativeFunction::strict(move |args| {
+                counter.set(counter.get() + 1);
+                args[0].clone() * &args[0]
+            }),
+        );
+        let code = parse::parse("countedSquare(3) + countedSquare(4)").unwrap();
+        compile::compile_into(code, &mut program).unwrap();
+        program.run(&mut env).unwrap();
+        drop(env);
+        assert!(String::from_utf8(output).unwrap().ends_with("25\n"));
+        assert_eq!(calls.get(), 2);
+    }
+}
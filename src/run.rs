@@ -1,15 +1,21 @@
+mod editor;
+mod history;
+mod save;
 mod special_command;
+mod watch;
 
-use crate::environment::Environment;
-use crate::program::{Program, ProgramError};
+use crate::environment::{Environment, ErrorKind, OutputMode};
+use crate::program::{DefinitionInfo, Program, ProgramError};
 use crate::{compile, parse};
+use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
 use std::ops::ControlFlow;
 use std::path::Path;
+use std::ptr;
 
 fn maybe_suggest_help(env: &mut Environment) -> Result<(), Box<dyn Error>> {
-    if env.io_options.suggest_help {
+    if env.io_options.suggest_help && env.io_options.output_mode == OutputMode::Text {
         writeln!(
             env.error_output(),
             "(For more information, type :help and press enter.)"
@@ -18,49 +24,181 @@ fn maybe_suggest_help(env: &mut Environment) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn run_str(code: &str, program: &mut Program, env: &mut Environment) -> Result<(), Box<dyn Error>> {
+// Runs code that was read from a file, or typed in if file is None.
+// Returns false if the code had errors, and wasn't run.
+fn run_str(
+    code: &str,
+    file: Option<&Path>,
+    program: &mut Program,
+    env: &mut Environment,
+) -> Result<bool, Box<dyn Error>> {
     env.ignore_ctrlc();
-    let code = match parse::parse(code) {
+    let mut code = match parse::parse(code) {
         Ok(code) => code,
         Err(err) => {
             if env.io_options.are_errors_fatal {
                 return Err(err.to_string().into());
             }
-            writeln!(env.error_output(), "{err}")?;
+            env.output_error(ErrorKind::Parse, &err, Some(err.line(code)))?;
             maybe_suggest_help(env)?;
-            return Ok(());
+            return Ok(false);
         }
     };
+    for source in &mut code.sources {
+        source.file = file.map(Path::to_path_buf);
+    }
     let backup = (!env.io_options.are_errors_fatal).then(|| program.clone());
     if let Err(err) = compile::compile_into(code, program) {
         if env.io_options.are_errors_fatal {
             return Err(err.into());
         }
-        writeln!(env.io_options.error_output, "{err}")?;
+        env.output_error(ErrorKind::Compilation, err, None)?;
         maybe_suggest_help(env)?;
         *program = backup.unwrap();
-        return Ok(());
+        return Ok(false);
+    }
+    if let Some(file) = file {
+        program.add_file(file);
     }
     let res = program.run(env);
     match res {
-        Ok(()) | Err(ProgramError::CtrlCError(_)) => Ok(()),
+        Ok(()) | Err(ProgramError::CtrlCError(_)) => Ok(true),
         Err(ProgramError::IoError(err)) => Err(err.into()),
     }
 }
 
+// Runs a file that was loaded before again, deletes the definitions that were removed from it,
+// and reports which definitions changed.
+fn reload(file: &Path, program: &mut Program, env: &mut Environment) -> Result<(), Box<dyn Error>> {
+    let code = match fs::read_to_string(file) {
+        Ok(code) => code,
+        Err(err) => {
+            let message = format!("{}: {err}", file.display());
+            env.output_error(ErrorKind::Command, message, None)?;
+            return Ok(());
+        }
+    };
+    let from_file = |info: &DefinitionInfo| {
+        info.source
+            .as_ref()
+            .is_some_and(|source| source.file.as_deref() == Some(file))
+    };
+    // The definitions from the file, and their text.
+    let old_definitions = program
+        .names()
+        .filter_map(|name| {
+            let info = program.get_info(name).filter(|&info| from_file(info))?;
+            let text = info.source.as_ref()?.text.clone();
+            Some((name.to_string(), (info as *const DefinitionInfo, text)))
+        })
+        .collect::<HashMap<_, _>>();
+    if !run_str(&code, Some(file), program, env)? {
+        return Ok(());
+    }
+    let mut changed = vec![];
+    let mut removed = vec![];
+    for (name, (old_info, old_text)) in &old_definitions {
+        match program.get_info(name) {
+            Some(info) if ptr::eq(info, *old_info) => removed.push(name.clone()),
+            Some(info) if from_file(info) && info.source.as_ref().unwrap().text != *old_text => {
+                changed.push(name.clone())
+            }
+            _ => {}
+        }
+    }
+    for name in &removed {
+        program.undefine(name).unwrap();
+    }
+    let mut added = program
+        .names()
+        .filter(|&name| !old_definitions.contains_key(name))
+        .filter(|&name| program.get_info(name).is_some_and(from_file))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    let mut changes = vec![];
+    for (description, names) in [
+        ("changed", &mut changed),
+        ("added", &mut added),
+        ("removed", &mut removed),
+    ] {
+        names.sort();
+        if !names.is_empty() {
+            changes.push(format!("{description} {}", names.join(", ")));
+        }
+    }
+    if changes.is_empty() {
+        changes.push("nothing changed".to_string());
+    }
+    writeln!(
+        env.output(),
+        "Reloaded {}: {}.",
+        file.display(),
+        changes.join("; ")
+    )?;
+    Ok(())
+}
+
 pub fn run_file(
     filename: &Path,
     program: &mut Program,
     env: &mut Environment,
 ) -> Result<(), Box<dyn Error>> {
     let code = fs::read_to_string(filename)?;
-    run_str(&code, program, env)
+    run_str(&code, Some(filename), program, env)?;
+    Ok(())
+}
+
+const PROMPT: &str = "recalc> ";
+const CONTINUATION_PROMPT: &str = "...> ";
+
+// Reads a line, including the newline at its end, or returns None at the end of the input.
+fn read_line(
+    editor: &mut Option<editor::LineEditor>,
+    program: &Program,
+    env: &mut Environment,
+    prompt: &str,
+) -> Result<Option<String>, Box<dyn Error>> {
+    if let Some(editor) = editor {
+        return Ok(editor.read_line(program, prompt)?.map(|line| line + "\n"));
+    }
+    if env.io_options.output_mode == OutputMode::Text {
+        write!(env.output(), "{prompt}")?;
+        env.output().flush()?;
+    }
+    let mut line = String::new();
+    if env.input().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line))
+}
+
+// What the REPL keeps track of besides the program.
+pub struct Session {
+    pub history: history::History,
+    pub watcher: Option<watch::Watcher>, // Created by the first :watch.
+}
+
+// Reloads the watched files that changed. It's called before each line runs, not while waiting for input.
+fn reload_changed_files(
+    session: &mut Session,
+    program: &mut Program,
+    env: &mut Environment,
+) -> Result<(), Box<dyn Error>> {
+    let Some(watcher) = &mut session.watcher else {
+        return Ok(());
+    };
+    for file in watcher.changed_files() {
+        session
+            .history
+            .record(program, |program| reload(&file, program, env))?;
+    }
+    Ok(())
 }
 
 pub fn repl(program: &mut Program, env: &mut Environment) -> Result<(), Box<dyn Error>> {
-    let mut line_buf = String::new();
     env.io_options.suggest_help = true;
-    if env.io_options.show_welcome_message {
+    env.io_options.show_result_numbers = true;
+    if env.io_options.show_welcome_message && env.io_options.output_mode == OutputMode::Text {
         writeln!(
             env.output(),
             "Recursive Calculator {}!\n\
@@ -68,20 +206,42 @@ pub fn repl(program: &mut Program, env: &mut Environment) -> Result<(), Box<dyn
             clap::crate_version!()
         )?;
     }
-    loop {
-        write!(env.output(), "recalc> ")?;
-        env.output().flush()?;
-        line_buf.clear();
-        if env.input().read_line(&mut line_buf)? == 0 {
-            break;
-        };
-        if line_buf.trim_start().starts_with(':') {
-            match special_command::run(&line_buf, program, env)? {
+    let mut session = Session {
+        history: history::History::new(program),
+        watcher: None,
+    };
+    let mut editor = if env.io_options.use_line_editor {
+        Some(editor::LineEditor::new()?)
+    } else {
+        None
+    };
+    while let Some(mut code) = read_line(&mut editor, program, env, PROMPT)? {
+        reload_changed_files(&mut session, program, env)?;
+        if code.trim() == ":{" {
+            // A block of lines, which are run together like a file, until :}.
+            code.clear();
+            while let Some(line) = read_line(&mut editor, program, env, CONTINUATION_PROMPT)? {
+                if line.trim() == ":}" {
+                    break;
+                }
+                code += &line;
+            }
+        } else if code.trim_start().starts_with(':') {
+            match special_command::run(&code, program, env, &mut session)? {
                 ControlFlow::Break(()) => break,
                 ControlFlow::Continue(()) => continue,
             }
+        } else {
+            while parse::is_incomplete(&code) {
+                match read_line(&mut editor, program, env, CONTINUATION_PROMPT)? {
+                    Some(line) => code += &line,
+                    None => break,
+                }
+            }
         }
-        run_str(&line_buf, program, env)?;
+        session
+            .history
+            .record(program, |program| run_str(&code, None, program, env))?;
     }
     Ok(())
 }
@@ -91,9 +251,24 @@ mod test {
     use super::*;
 
     fn assert_repl(input: &str, expected_output: &str, expected_error_output: &str) {
+        assert_repl_with_mode(
+            OutputMode::Text,
+            input,
+            expected_output,
+            expected_error_output,
+        );
+    }
+
+    fn assert_repl_with_mode(
+        mode: OutputMode,
+        input: &str,
+        expected_output: &str,
+        expected_error_output: &str,
+    ) {
         let mut output = Vec::<u8>::new();
         let mut error_output = Vec::<u8>::new();
         let mut env = Environment::default();
+        env.io_options.output_mode = mode;
         env.io_options.input = Box::new(input.as_bytes());
         env.io_options.output = Box::new(&mut output);
         env.io_options.error_output = Box::new(&mut error_output);
@@ -107,13 +282,288 @@ mod test {
         );
     }
 
+    #[test]
+    fn calculus_test() {
+        assert_repl(
+            "\
+                :solve x^2 = 9/4 near 1\n\
+                :solve x^2 + 1 between 0 and 3\n\
+                :solve floor(x) - 1/2 between 0 and 3\n\
+                :solve h^2 = 9/4 near 1\n\
+                f(x) = x^3 - 2*x\n\
+                :diff f\n\
+                :diff g\n\
+            ",
+            "\
+                recalc> \
+                1.5\n\
+                recalc> \
+                Undefined result: no sign change found\n\
+                recalc> \
+                Undefined result: the sign change isn't a root\n\
+                recalc> \
+                1.5\n\
+                recalc> \
+                recalc> \
+                f'(x) = 3*x^2 - 2\n\
+                recalc> \
+                recalc> \
+            ",
+            "function not found: g\n",
+        );
+    }
+
+    #[test]
+    fn multi_line_test() {
+        assert_repl(
+            "\
+                f(x) = if(\n\
+                    x, ** the\n\
+                    comment ** 1,\n\
+                    2)\n\
+                [f(0), f(1)]\n\
+                :{\n\
+                even(n) = if(n, odd(n - 1), 1)\n\
+                odd(n) = if(n, even(n - 1), 0)\n\
+                :}\n\
+                even(10)\n\
+            ",
+            "\
+                recalc> ...> ...> ...> \
+                recalc> $1 = [2, 1]\n\
+                recalc> ...> ...> ...> \
+                recalc> $2 = 1\n\
+                recalc> \
+            ",
+            "",
+        );
+    }
+
+    #[test]
+    fn introspection_test() {
+        assert_repl(
+            "\
+                :{\n\
+                ** The factorial of n. **\n\
+                fact(n) = if(n, n*fact(n - 1), 1)\n\
+                a = fact(3) + b\n\
+                b = 2\n\
+                :}\n\
+                b\n\
+                :list\n\
+                :list a*\n\
+                :show a\n\
+                :show --latex fact\n\
+                :info fact\n\
+                :info b\n\
+                :info isqrt\n\
+                :info c\n\
+            ",
+            "\
+                recalc> ...> ...> ...> ...> ...> \
+                recalc> $1 = 2\n\
+                recalc> \
+                a     constant, not evaluated\n\
+                b     constant, evaluated\n\
+                fact  function, 1 parameter\n\
+                recalc> \
+                a  constant, not evaluated\n\
+                recalc> \
+                a = fact(3) + b\n\
+                recalc> \
+                \\mathrm{fact}\\left(n\\right) = \\mathrm{if}\\left(n, n \\cdot \\mathrm{fact}\\left(n - 1\\right), 1\\right)\n\
+                recalc> \
+                fact(n) = if(n, n*fact(n - 1), 1)\n\
+                The factorial of n.\n\
+                Defined in the REPL.\n\
+                Depends on: fact, if.\n\
+                recalc> \
+                b = 2\n\
+                Defined in the REPL.\n\
+                It doesn't depend on anything.\n\
+                recalc> \
+                isqrt is a built-in function with 1 parameter.\n\
+                recalc> \
+                recalc> \
+            ",
+            "no constant or function named \"c\"\n",
+        );
+    }
+
+    #[test]
+    fn undo_test() {
+        assert_repl(
+            "\
+                x = 1\n\
+                :checkpoint one\n\
+                x = 2\n\
+                :delete x\n\
+                :undo\n\
+                x\n\
+                :undo\n\
+                x\n\
+                y = 3\n\
+                :restore one\n\
+                :checkpoint\n\
+                :restore two\n\
+                :reset\n\
+                :undo\n\
+                :undo\n\
+                :undo\n\
+                :undo\n\
+                :undo\n\
+                x\n\
+            ",
+            "\
+                recalc> \
+                recalc> \
+                recalc> \
+                recalc> \
+                recalc> \
+                recalc> $1 = 2\n\
+                recalc> \
+                recalc> $2 = 1\n\
+                recalc> \
+                recalc> \
+                recalc> Checkpoints: one.\n\
+                recalc> \
+                recalc> \
+                recalc> \
+                recalc> \
+                recalc> \
+                recalc> \
+                recalc> \
+                recalc> \
+                recalc> \
+            ",
+            "no checkpoint named \"two\"\nnothing to undo\nconstant not found: x\n(For more information, type :help and press enter.)\n",
+        );
+    }
+
+    #[test]
+    fn reload_test() {
+        let dir = std::env::temp_dir().join(format!("recalc_reload_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("lib.recalc");
+        fs::write(&file, "a = 1\nb = 2\nf(x) = x\n").unwrap();
+        let mut output = Vec::<u8>::new();
+        let mut env = Environment::default();
+        env.io_options.output = Box::new(&mut output);
+        let mut program = Program::new();
+        run_file(&file, &mut program, &mut env).unwrap();
+        fs::write(&file, "a = 1\nb = 3\ng(x) = 2*x\n").unwrap();
+        reload(&file, &mut program, &mut env).unwrap();
+        reload(&file, &mut program, &mut env).unwrap();
+        assert!(program.get_function("f").is_none());
+        assert!(program.get_function("g").is_some());
+        assert_eq!(program.files(), std::slice::from_ref(&file));
+        drop(env);
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            format!(
+                "Reloaded {0}: changed b; added g; removed f.\nReloaded {0}: nothing changed.\n",
+                file.display()
+            )
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn live_test() {
+        assert_repl(
+            "\
+                f(x) = x^2\n\
+                g(x) = f(x) + 1\n\
+                c = a = g(2)\n\
+                c\n\
+                f(x) = x + 1\n\
+                g(2)\n\
+                :redefine f(x) = x + 3\n\
+                [g(2), c, a]\n\
+                :show a\n\
+                :live on\n\
+                f(x) = 2*x\n\
+                c\n\
+                f(x, y) = x\n\
+                :live\n\
+            ",
+            "\
+                recalc> \
+                recalc> \
+                recalc> \
+                recalc> $1 = 5\n\
+                recalc> \
+                recalc> $2 = 5\n\
+                recalc> \
+                recalc> $3 = [6, 6, 6]\n\
+                recalc> c = a = g(2)\n\
+                recalc> \
+                recalc> \
+                recalc> $4 = 5\n\
+                recalc> \
+                recalc> Live mode is on.\n\
+                recalc> \
+            ",
+            "while recompiling g, a, c, which depend on a replaced definition: \
+            function \"f\" got 1 argument(s) instead of 2\n\
+            (For more information, type :help and press enter.)\n",
+        );
+    }
+
+    #[test]
+    fn result_history_test() {
+        assert_repl(
+            "\
+                2 + 3\n\
+                ans*2\n\
+                $1 + _\n\
+                f(x) = x + $2\n\
+                f(1) + ans\n\
+            ",
+            "\
+                recalc> $1 = 5\n\
+                recalc> $2 = 10\n\
+                recalc> $3 = 15\n\
+                recalc> \
+                recalc> $4 = 26\n\
+                recalc> \
+            ",
+            "",
+        );
+    }
+
+    #[test]
+    fn json_test() {
+        assert_repl_with_mode(
+            OutputMode::Json,
+            "\
+                x = -1/2\n\
+                x + 1\n\
+                0/0\n\
+                [1, 2)\n\
+                y\n\
+            ",
+            "\
+                {\"result\":1,\"line\":1,\"source\":\"x + 1\",\"type\":\"number\",\"numerator\":\"1\",\
+                \"denominator\":\"2\",\"decimal\":\"0.5\",\"text\":\"0.5\"}\n\
+                {\"result\":2,\"line\":1,\"source\":\"0/0\",\"type\":\"undefined\",\"undefined\":\"zero_over_zero\",\
+                \"message\":\"Undefined result: zero divided by zero\",\
+                \"text\":\"Undefined result: zero divided by zero\"}\n\
+            ",
+            "\
+                {\"error\":\"parse\",\"message\":\"Unexpected character: '['\",\"line\":1}\n\
+                {\"error\":\"compilation\",\"message\":\"constant not found: y\",\"line\":null}\n\
+            ",
+        );
+    }
+
     #[test]
     fn repl_test() {
         assert_repl(
             "1 + 1",
             "\
                 recalc> \
-                2\n\
+                $1 = 2\n\
                 recalc> \
             ",
             "",
@@ -126,7 +576,7 @@ mod test {
             "\
                 recalc> \
                 recalc> \
-                12\n\
+                $1 = 12\n\
                 recalc> \
             ",
             "",
@@ -141,7 +591,7 @@ mod test {
                 recalc> \
                 recalc> \
                 recalc> \
-                120\n\
+                $1 = 120\n\
                 recalc> \
             ",
             "",
@@ -166,12 +616,12 @@ mod test {
                 recalc> \
                 recalc> \
                 recalc> \
-                3\n\
+                $1 = 3\n\
                 recalc> \
                 recalc> \
-                4\n\
+                $2 = 4\n\
                 recalc> \
-                4\n\
+                $3 = 4\n\
                 recalc> \
             ",
             "constant not found: y\n(For more information, type :help and press enter.)\n",
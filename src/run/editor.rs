@@ -0,0 +1,241 @@
+// The interactive line editor, with history and completion.
+
+use super::special_command::COMMANDS;
+use crate::program::Program;
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{CompletionType, Config, Context, Editor};
+use std::fs;
+use std::path::PathBuf;
+
+struct Name {
+    name: String,
+    params: Option<String>, // The parameters of a function defined in recalc, e.g. "x, y".
+    is_function: bool,
+}
+
+pub struct Helper {
+    names: Vec<Name>,
+    filename_completer: FilenameCompleter,
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '\''
+}
+
+// The start of the name that ends at the end of the text.
+fn name_start(text: &str) -> usize {
+    text.char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_identifier_char(c))
+        .last()
+        .map_or(text.len(), |(i, _)| i)
+}
+
+impl Helper {
+    fn new(program: &Program) -> Self {
+        let mut names = program
+            .names()
+            .map(|name| Name {
+                name: name.to_string(),
+                params: program
+                    .get_function(name)
+                    .and_then(|function| function.get())
+                    .map(|function| function.params().join(", ")),
+                is_function: program.get_n_params(name).is_some(),
+            })
+            .collect::<Vec<_>>();
+        names.sort_by(|a, b| a.name.cmp(&b.name));
+        Self {
+            names,
+            filename_completer: FilenameCompleter::new(),
+        }
+    }
+
+    fn complete_name(&self, prefix: &str, with_parenthesis: bool) -> Vec<Pair> {
+        self.names
+            .iter()
+            .filter(|name| name.name.starts_with(prefix))
+            .map(|name| match (&name.params, name.is_function) {
+                (_, false) => Pair {
+                    display: name.name.clone(),
+                    replacement: name.name.clone(),
+                },
+                (params, true) => Pair {
+                    display: format!("{}({})", name.name, params.as_deref().unwrap_or("...")),
+                    replacement: if with_parenthesis {
+                        format!("{}(", name.name)
+                    } else {
+                        name.name.clone()
+                    },
+                },
+            })
+            .collect()
+    }
+}
+
+impl Completer for Helper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let Some(command) = before.trim_start().strip_prefix(':') else {
+            let start = name_start(before);
+            return Ok((start, self.complete_name(&before[start..], true)));
+        };
+        let command_start = pos - command.len();
+        match command.split_once(char::is_whitespace) {
+            None => {
+                let commands = COMMANDS
+                    .iter()
+                    .filter(|name| name.starts_with(command))
+                    .map(|name| Pair {
+                        display: name.to_string(),
+                        replacement: name.to_string(),
+                    })
+                    .collect();
+                Ok((command_start, commands))
+            }
+            Some(("l" | "load" | "watch" | "save", _)) => {
+                self.filename_completer.complete(line, pos, ctx)
+            }
+            Some(("d" | "delete" | "diff" | "list" | "show" | "info" | "solve", _)) => {
+                let start = name_start(before);
+                Ok((start, self.complete_name(&before[start..], false)))
+            }
+            Some(_) => Ok((pos, vec![])),
+        }
+    }
+}
+
+impl Hinter for Helper {
+    type Hint = String;
+
+    // The parameters of a function after its name and an opening parenthesis, e.g. f( shows x, y).
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<String> {
+        let before = line[..pos].strip_suffix('(')?;
+        if pos < line.len() {
+            return None;
+        }
+        let name = &before[name_start(before)..];
+        let params = self
+            .names
+            .iter()
+            .find(|n| n.name == name)?
+            .params
+            .as_ref()?;
+        Some(format!("{params})"))
+    }
+}
+
+impl Highlighter for Helper {}
+
+impl Validator for Helper {}
+
+impl rustyline::Helper for Helper {}
+
+pub struct LineEditor {
+    editor: Editor<Helper, FileHistory>,
+    history_file: Option<PathBuf>,
+}
+
+impl LineEditor {
+    pub fn new() -> rustyline::Result<Self> {
+        let config = Config::builder()
+            .completion_type(CompletionType::List)
+            .auto_add_history(false)
+            .build();
+        let mut editor = Editor::with_config(config)?;
+        let history_file = dirs::data_dir().and_then(|dir| {
+            let dir = dir.join("recalc");
+            fs::create_dir_all(&dir).ok()?;
+            Some(dir.join("history.txt"))
+        });
+        if let Some(history_file) = &history_file {
+            // There's no history the first time.
+            let _ = editor.load_history(history_file);
+        }
+        Ok(Self {
+            editor,
+            history_file,
+        })
+    }
+
+    // Reads a line, or returns None at the end of the input.
+    pub fn read_line(
+        &mut self,
+        program: &Program,
+        prompt: &str,
+    ) -> rustyline::Result<Option<String>> {
+        self.editor.set_helper(Some(Helper::new(program)));
+        loop {
+            match self.editor.readline(prompt) {
+                Ok(line) => {
+                    if !line.trim().is_empty() {
+                        self.editor.add_history_entry(&line)?;
+                        if let Some(history_file) = &self.history_file {
+                            self.editor.append_history(history_file)?;
+                        }
+                    }
+                    return Ok(Some(line));
+                }
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => return Ok(None),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compile;
+    use crate::parse;
+    use rustyline::history::DefaultHistory;
+
+    fn complete(helper: &Helper, line: &str) -> (usize, Vec<String>) {
+        let history = DefaultHistory::new();
+        let (start, pairs) = helper
+            .complete(line, line.len(), &Context::new(&history))
+            .unwrap();
+        (start, pairs.into_iter().map(|p| p.replacement).collect())
+    }
+
+    #[test]
+    fn complete_test() {
+        let mut program = Program::new();
+        let code = parse::parse("fact(n) = if(n, n*fact(n - 1), 1)\nfactor = 3").unwrap();
+        compile::compile_into(code, &mut program).unwrap();
+        let helper = Helper::new(&program);
+        assert_eq!(
+            complete(&helper, "1 + fac"),
+            (4, vec!["fact(".to_string(), "factor".to_string()])
+        );
+        assert_eq!(complete(&helper, ":sh"), (1, vec!["show".to_string()]));
+        assert_eq!(
+            complete(&helper, " :s"),
+            (
+                2,
+                vec!["save".to_string(), "solve".to_string(), "show".to_string()]
+            )
+        );
+        assert_eq!(
+            complete(&helper, ":show fa"),
+            (6, vec!["fact".to_string(), "factor".to_string()])
+        );
+        let history = DefaultHistory::new();
+        let ctx = Context::new(&history);
+        assert_eq!(helper.hint("2*fact(", 7, &ctx), Some("n)".to_string()));
+        assert_eq!(helper.hint("2*factor(", 9, &ctx), None);
+    }
+}
@@ -0,0 +1,131 @@
+// Earlier states of the program, for undoing changes and jumping between checkpoints.
+
+use crate::program::Program;
+use std::collections::HashMap;
+
+const MAX_UNDO: usize = 100;
+
+pub struct History {
+    undo: Vec<Program>, // The states before the last changes, the most recent one last.
+    checkpoints: HashMap<String, Program>,
+    libraries: Program, // The state with only the libraries that were loaded on the command line.
+}
+
+impl History {
+    pub fn new(libraries: &Program) -> Self {
+        Self {
+            undo: vec![],
+            checkpoints: HashMap::new(),
+            libraries: libraries.clone(),
+        }
+    }
+
+    // Remembers a state from before a change.
+    pub fn push(&mut self, state: Program) {
+        if self.undo.len() == MAX_UNDO {
+            self.undo.remove(0);
+        }
+        self.undo.push(state);
+    }
+
+    // Runs f, and remembers the state before it if it changed the definitions.
+    pub fn record<T>(&mut self, program: &mut Program, f: impl FnOnce(&mut Program) -> T) -> T {
+        let backup = program.clone();
+        let result = f(program);
+        if program.version() != backup.version() {
+            self.push(backup);
+        }
+        result
+    }
+
+    // Returns false if there's nothing to undo.
+    pub fn undo(&mut self, program: &mut Program) -> bool {
+        let Some(state) = self.undo.pop() else {
+            return false;
+        };
+        program.restore(&state);
+        true
+    }
+
+    // Goes back to the libraries, or to no definitions but the builtin ones if only_builtins is set.
+    pub fn reset(&mut self, program: &mut Program, only_builtins: bool) {
+        let state = if only_builtins {
+            Program::new()
+        } else {
+            self.libraries.clone()
+        };
+        self.record(program, |program| program.restore(&state));
+    }
+
+    pub fn checkpoint(&mut self, name: &str, program: &Program) {
+        self.checkpoints.insert(name.to_string(), program.clone());
+    }
+
+    // Returns false if there's no checkpoint with that name.
+    pub fn restore(&mut self, name: &str, program: &mut Program) -> bool {
+        let Some(state) = self.checkpoints.get(name).cloned() else {
+            return false;
+        };
+        self.record(program, |program| program.restore(&state));
+        true
+    }
+
+    pub fn checkpoint_names(&self) -> Vec<&str> {
+        let mut names = self
+            .checkpoints
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::environment::Environment;
+    use crate::{compile, parse};
+
+    fn run(code: &str, program: &mut Program) {
+        compile::compile_into(parse::parse(code).unwrap(), program).unwrap();
+        let mut env = Environment::default();
+        env.io_options.output = Box::new(std::io::sink());
+        program.run(&mut env).unwrap();
+    }
+
+    fn value(name: &str, program: &Program) -> Option<String> {
+        Some(program.get_constant(name)?.value_if_found()?.to_string())
+    }
+
+    #[test]
+    fn history_test() {
+        let mut program = Program::new();
+        run("lib = 1", &mut program);
+        let mut history = History::new(&program);
+        history.record(&mut program, |program| run("x = 2^10", program));
+        history.checkpoint("one", &program);
+        // Evaluated after the checkpoint, but the value is shared with it.
+        history.record(&mut program, |program| run("x", program));
+        history.record(&mut program, |program| run("x = 3\ny = 4", program));
+        assert_eq!(value("$1", &program), Some("1024".to_string()));
+        assert!(history.restore("one", &mut program));
+        assert_eq!(value("x", &program), Some("1024".to_string()));
+        assert!(program.get_constant("y").is_none());
+        assert!(history.undo(&mut program));
+        assert_eq!(value("y", &program), None);
+        assert!(program.get_constant("y").is_some());
+        history.reset(&mut program, false);
+        assert!(program.get_constant("x").is_none());
+        assert!(program.get_constant("lib").is_some());
+        assert_eq!(value("$1", &program), Some("1024".to_string()));
+        history.reset(&mut program, true);
+        assert!(program.get_constant("lib").is_none());
+        for _ in 0..4 {
+            assert!(history.undo(&mut program));
+        }
+        assert!(program.get_constant("x").is_none());
+        assert!(!history.undo(&mut program));
+        assert!(!history.restore("two", &mut program));
+    }
+}
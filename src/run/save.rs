@@ -0,0 +1,206 @@
+// Writes the definitions of a session as code that can be loaded again.
+
+use crate::math::format::{Format, FormattedValue};
+use crate::program::{DefinitionInfo, Program};
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::ptr;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Options {
+    pub transcript: bool, // Also write the code that was evaluated, with the results in comments.
+    pub skip_loaded: bool, // Don't write the definitions that were loaded from files.
+}
+
+struct Writer<'a> {
+    program: &'a Program,
+    options: Options,
+    written: HashSet<String>,
+    code: String,
+}
+
+impl<'a> Writer<'a> {
+    // Writes a definition after the ones it depends on.
+    fn write(&mut self, name: &str) {
+        if !self.written.insert(name.to_string()) {
+            return;
+        }
+        let Some(info) = self.program.get_info(name) else {
+            // A result that a definition refers to, which is written as its value.
+            if let Some(value) = self
+                .program
+                .get_constant(name)
+                .and_then(|c| c.value_if_found())
+            {
+                let value = FormattedValue(Format::Fraction, value);
+                writeln!(self.code, "{name} = {value}").unwrap();
+            }
+            return;
+        };
+        if self.options.skip_loaded && info.source.as_ref().is_some_and(|s| s.file.is_some()) {
+            return;
+        }
+        // A statement like a = b = 1 is written once, if it still defines all its names.
+        let is_whole_statement = info.names.iter().all(|name| {
+            self.program
+                .get_info(name)
+                .is_some_and(|other| ptr::eq::<DefinitionInfo>(other, info))
+        });
+        let names = if is_whole_statement {
+            info.names.clone()
+        } else {
+            vec![name.to_string()]
+        };
+        for name in &names {
+            self.written.insert(name.clone());
+            for dependency in self.program.dependencies(name) {
+                self.write(&dependency);
+            }
+        }
+        match &info.source {
+            Some(source) if is_whole_statement => {
+                if let Some(doc) = &source.doc {
+                    if !self.code.is_empty() {
+                        self.code.push('\n');
+                    }
+                    writeln!(self.code, "** {doc} **").unwrap();
+                }
+                writeln!(self.code, "{}", source.text).unwrap();
+            }
+            _ => {
+                let left = self.program.left_side(name);
+                writeln!(self.code, "{left} = {}", info.value).unwrap();
+            }
+        }
+    }
+}
+
+// The code that defines the constants and functions of the program, each after the ones it
+// depends on, with their original text and comments.
+pub fn save(program: &Program, options: Options) -> String {
+    let mut names = program
+        .names()
+        .filter(|name| program.get_info(name).is_some())
+        .collect::<Vec<_>>();
+    names.sort();
+    let mut writer = Writer {
+        program,
+        options,
+        written: HashSet::new(),
+        code: String::new(),
+    };
+    for name in names {
+        writer.write(name);
+    }
+    let mut code = writer.code;
+    if options.transcript && !program.transcript().is_empty() {
+        if !code.is_empty() {
+            code.push('\n');
+        }
+        for (number, source) in program.transcript() {
+            write!(code, "{}", source.text).unwrap();
+            let result = program.get_constant(&format!("${number}"));
+            match result.and_then(|constant| constant.value_if_found()) {
+                Some(value) => writeln!(
+                    code,
+                    " ** ${number} = {} **",
+                    FormattedValue(Format::Fraction, value)
+                )
+                .unwrap(),
+                None => writeln!(code).unwrap(),
+            }
+        }
+    }
+    code
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::environment::Environment;
+    use crate::{compile, parse};
+    use std::path::Path;
+
+    fn run(code: &str, file: Option<&Path>, program: &mut Program) {
+        let mut code = parse::parse(code).unwrap();
+        for source in &mut code.sources {
+            source.file = file.map(Path::to_path_buf);
+        }
+        compile::compile_into(code, program).unwrap();
+        let mut env = Environment::default();
+        env.io_options.output = Box::new(std::io::sink());
+        program.run(&mut env).unwrap();
+    }
+
+    #[test]
+    fn save_test() {
+        let mut program = Program::new();
+        run(
+            "** The library. **\nlib(x) = 2*x",
+            Some(Path::new("lib.recalc")),
+            &mut program,
+        );
+        run("1 + 1", None, &mut program);
+        run(
+            "\
+            ** Uses c and lib.\n   Twice. **\n\
+            f(x) = lib(c) + x*$1\n\
+            c = d = 3\n\
+            a = b = [4, 5]\n\
+            ",
+            None,
+            &mut program,
+        );
+        run("b = 6\nf(1)", None, &mut program);
+        let options = Options {
+            transcript: false,
+            skip_loaded: false,
+        };
+        assert_eq!(
+            save(&program, options),
+            "\
+            a = [4, 5]\n\
+            b = 6\n\
+            c = d = 3\n\
+            $1 = 2\n\
+            \n\
+            ** The library. **\n\
+            lib(x) = 2*x\n\
+            \n\
+            ** Uses c and lib.\nTwice. **\n\
+            f(x) = lib(c) + x*$1\n\
+            "
+        );
+        // The saved code defines the same things when it's loaded again.
+        let mut loaded = Program::new();
+        run(&save(&program, options), None, &mut loaded);
+        let sorted_lines = |program| {
+            let mut lines = save(program, options)
+                .lines()
+                .map(String::from)
+                .collect::<Vec<_>>();
+            lines.sort();
+            lines
+        };
+        assert_eq!(sorted_lines(&loaded), sorted_lines(&program));
+        let options = Options {
+            transcript: true,
+            skip_loaded: true,
+        };
+        assert_eq!(
+            save(&program, options),
+            "\
+            a = [4, 5]\n\
+            b = 6\n\
+            c = d = 3\n\
+            $1 = 2\n\
+            \n\
+            ** Uses c and lib.\nTwice. **\n\
+            f(x) = lib(c) + x*$1\n\
+            \n\
+            1 + 1 ** $1 = 2 **\n\
+            f(1) ** $2 = 8 **\n\
+            "
+        );
+    }
+}
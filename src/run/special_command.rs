@@ -1,22 +1,35 @@
 use std::error::Error;
 use std::fs;
 use std::ops::ControlFlow;
+use std::path::Path;
 use std::str::FromStr;
 
-use crate::environment::Environment;
-use crate::math::format;
-use crate::program::Program;
+use super::save;
+use super::{watch, Session};
+use crate::environment::{Environment, ErrorKind};
+use crate::expression::Expression;
+use crate::math::markup::Markup;
+use crate::math::{format, solve, Value};
+use crate::parse::{self as p, Statement};
+use crate::program::{LazyExpression, Program};
+use crate::{compile, derivative, parse};
+use malachite::Rational;
 
 pub type Result = std::result::Result<ControlFlow<()>, Box<dyn Error>>;
 
 trait Command {
-    fn run(&self, program: &mut Program, env: &mut Environment) -> Result;
+    fn run(&self, program: &mut Program, env: &mut Environment, session: &mut Session) -> Result;
 }
 
 struct Quit;
 
 impl Command for Quit {
-    fn run(&self, _program: &mut Program, _env: &mut Environment) -> Result {
+    fn run(
+        &self,
+        _program: &mut Program,
+        _env: &mut Environment,
+        _session: &mut Session,
+    ) -> Result {
         Ok(ControlFlow::Break(()))
     }
 }
@@ -26,19 +39,21 @@ struct Format {
 }
 
 impl Command for Format {
-    fn run(&self, _program: &mut Program, env: &mut Environment) -> Result {
+    fn run(&self, _program: &mut Program, env: &mut Environment, _session: &mut Session) -> Result {
         if self.new_format.is_empty() {
             let fmt = env.io_options.output_format;
             writeln!(env.output(), "The current format is: {}.", fmt,)?;
             writeln!(
                 env.output(),
-                "Type :format fraction, :format mixed or :format scientific to change it."
+                "Type :format fraction, :format mixed, :format scientific, :format continued, \
+                :format base <n>, :format latex, :format mathml, :format digits <n>, \
+                :format decimals <n> or :format engineering <n> to change it."
             )?;
             return Ok(ControlFlow::Continue(()));
         }
         match format::Format::from_str(&self.new_format) {
             Ok(fmt) => env.io_options.output_format = fmt,
-            Err(err) => writeln!(env.error_output(), "{err}")?,
+            Err(err) => env.output_error(ErrorKind::Command, err, None)?,
         };
         Ok(ControlFlow::Continue(()))
     }
@@ -49,13 +64,14 @@ struct Delete {
 }
 
 impl Command for Delete {
-    fn run(&self, program: &mut Program, env: &mut Environment) -> Result {
-        if program.undefine(&self.name).is_err() {
-            writeln!(
-                env.error_output(),
-                "no constant or function named \"{}\"",
-                self.name
-            )?;
+    fn run(&self, program: &mut Program, env: &mut Environment, session: &mut Session) -> Result {
+        if session
+            .history
+            .record(program, |program| program.undefine(&self.name))
+            .is_err()
+        {
+            let message = format!("no constant or function named \"{}\"", self.name);
+            env.output_error(ErrorKind::Command, message, None)?;
         }
         Ok(ControlFlow::Continue(()))
     }
@@ -65,16 +81,581 @@ struct Load {
     file: String,
 }
 
+// Returns false if the file couldn't be read or had errors.
+fn load(
+    file: &Path,
+    program: &mut Program,
+    env: &mut Environment,
+    session: &mut Session,
+) -> std::result::Result<bool, Box<dyn Error>> {
+    let code = match fs::read_to_string(file) {
+        Ok(code) => code,
+        Err(err) => {
+            env.output_error(ErrorKind::Command, err, None)?;
+            return Ok(false);
+        }
+    };
+    session.history.record(program, |program| {
+        super::run_str(&code, Some(file), program, env)
+    })
+}
+
 impl Command for Load {
-    fn run(&self, program: &mut Program, env: &mut Environment) -> Result {
-        let code = match fs::read_to_string(&self.file) {
-            Ok(code) => code,
+    fn run(&self, program: &mut Program, env: &mut Environment, session: &mut Session) -> Result {
+        load(Path::new(&self.file), program, env, session)?;
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+struct Reload;
+
+impl Command for Reload {
+    fn run(&self, program: &mut Program, env: &mut Environment, session: &mut Session) -> Result {
+        let files = program.files().to_vec();
+        if files.is_empty() {
+            writeln!(env.output(), "No files have been loaded.")?;
+        }
+        session.history.record(program, |program| {
+            files
+                .iter()
+                .try_for_each(|file| super::reload(file, program, env))
+        })?;
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+struct Watch {
+    file: String,
+}
+
+impl Command for Watch {
+    fn run(&self, program: &mut Program, env: &mut Environment, session: &mut Session) -> Result {
+        if self.file.is_empty() {
+            let files = session.watcher.iter().flat_map(|watcher| watcher.files());
+            let files = files
+                .map(|file| file.display().to_string())
+                .collect::<Vec<_>>();
+            if files.is_empty() {
+                writeln!(env.output(), "No files are being watched.")?;
+            } else {
+                writeln!(env.output(), "Watching: {}.", files.join(", "))?;
+            }
+            return Ok(ControlFlow::Continue(()));
+        }
+        let file = Path::new(&self.file);
+        let is_loaded = program.files().iter().any(|f| f == file);
+        if !is_loaded && !load(file, program, env, session)? {
+            return Ok(ControlFlow::Continue(()));
+        }
+        let watcher = match &mut session.watcher {
+            Some(watcher) => watcher,
+            None => match watch::Watcher::new() {
+                Ok(watcher) => session.watcher.insert(watcher),
+                Err(err) => {
+                    env.output_error(ErrorKind::Command, err, None)?;
+                    return Ok(ControlFlow::Continue(()));
+                }
+            },
+        };
+        if let Err(err) = watcher.watch(file) {
+            env.output_error(ErrorKind::Command, err, None)?;
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+struct Live {
+    args: String,
+}
+
+impl Command for Live {
+    fn run(&self, program: &mut Program, env: &mut Environment, _session: &mut Session) -> Result {
+        match &self.args[..] {
+            "" => {
+                let state = if program.is_live() { "on" } else { "off" };
+                writeln!(env.output(), "Live mode is {state}.")?;
+            }
+            "on" => program.set_live(true),
+            "off" => program.set_live(false),
+            _ => env.output_error(ErrorKind::Command, "usage: :live [on | off]", None)?,
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+struct Redefine {
+    code: String,
+}
+
+impl Command for Redefine {
+    fn run(&self, program: &mut Program, env: &mut Environment, session: &mut Session) -> Result {
+        let live = program.is_live();
+        program.set_live(true);
+        let result = session.history.record(program, |program| {
+            super::run_str(&self.code, None, program, env)
+        });
+        program.set_live(live);
+        result?;
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+struct Save {
+    args: String,
+}
+
+impl Command for Save {
+    fn run(&self, program: &mut Program, env: &mut Environment, _session: &mut Session) -> Result {
+        let mut options = save::Options::default();
+        let mut file = &self.args[..];
+        while let Some((option, rest)) = file.split_once(char::is_whitespace) {
+            match option {
+                "--transcript" => options.transcript = true,
+                "--skip-loaded" => options.skip_loaded = true,
+                _ => break,
+            }
+            file = rest.trim_start();
+        }
+        if file.is_empty() || file.starts_with("--") {
+            let usage = "usage: :save [--transcript] [--skip-loaded] <filename>";
+            env.output_error(ErrorKind::Command, usage, None)?;
+            return Ok(ControlFlow::Continue(()));
+        }
+        if let Err(err) = fs::write(file, save::save(program, options)) {
+            env.output_error(ErrorKind::Command, err, None)?;
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+struct Diff {
+    args: String,
+}
+
+impl Command for Diff {
+    fn run(&self, program: &mut Program, env: &mut Environment, session: &mut Session) -> Result {
+        let mut args = self.args.split_whitespace();
+        let Some(function) = args.next() else {
+            let usage = "usage: :diff <function> [<parameter>]";
+            env.output_error(ErrorKind::Command, usage, None)?;
+            return Ok(ControlFlow::Continue(()));
+        };
+        let derivatives = match derivative::differentiate(program, function, args.next()) {
+            Ok(derivatives) => derivatives,
             Err(err) => {
-                writeln!(env.error_output(), "{err}")?;
+                env.output_error(ErrorKind::Command, err, None)?;
+                return Ok(ControlFlow::Continue(()));
+            }
+        };
+        let code = parse::Code {
+            statements: derivatives.iter().map(|d| d.to_statement()).collect(),
+            sources: vec![],
+        };
+        let backup = program.clone();
+        if let Err(err) = compile::compile_into(code, program) {
+            env.output_error(ErrorKind::Command, err, None)?;
+            *program = backup;
+            return Ok(ControlFlow::Continue(()));
+        }
+        session.history.push(backup);
+        for derivative in &derivatives {
+            writeln!(env.output(), "{derivative}")?;
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+struct Solve {
+    args: String,
+}
+
+// Splits the text around the last occurrence of a keyword that's surrounded by spaces.
+fn split_keyword<'a>(text: &'a str, keyword: &str) -> Option<(&'a str, &'a str)> {
+    let i = text.rfind(&format!(" {keyword} "))?;
+    Some((&text[..i], &text[i + keyword.len() + 2..]))
+}
+
+fn parse_expression(code: &str) -> std::result::Result<p::Expression, Box<dyn Error>> {
+    let code = parse::parse(code).map_err(|err| err.to_string())?;
+    match <[Statement; 1]>::try_from(code.statements) {
+        Ok([Statement::Evaluate(expr)]) => Ok(expr),
+        _ => Err("expected one expression".into()),
+    }
+}
+
+fn is_defined(name: &str, program: &Program) -> bool {
+    program.get_constant(name).is_some()
+        || program.get_builtin_constant(name).is_some()
+        || program.get_n_params(name).is_some()
+}
+
+// Adds the identifiers in expr that aren't defined in the program to unknowns.
+fn find_unknowns(expr: &p::Expression, program: &Program, unknowns: &mut Vec<String>) {
+    expr.for_each_name(&mut |name, is_call| {
+        if !is_call && !is_defined(name, program) && !unknowns.contains(name) {
+            unknowns.push(name.clone());
+        }
+    });
+}
+
+// Adds the names of built-in units in expr to unknowns, except for units of numbers, like the m in 3 m,
+// and units after in. A unit name that's used on its own, like h in h^2 = 2, is probably meant as an unknown.
+fn find_unit_unknowns(expr: &p::Expression, program: &Program, unknowns: &mut Vec<String>) {
+    use p::Expression::*;
+    let is_unit = |expr: &p::Expression| match expr {
+        Identifier(_) => true,
+        Pow(base, _) => matches!(**base, Identifier(_)),
+        _ => false,
+    };
+    let mut find = |expr| find_unit_unknowns(expr, program, unknowns);
+    match expr {
+        Identifier(name) => {
+            if matches!(program.get_builtin_constant(name), Some(Value::Quantity(_)))
+                && !unknowns.contains(name)
+            {
+                unknowns.push(name.clone());
+            }
+        }
+        Mul(left, right) if matches!(**left, Number(_)) && is_unit(right) => {}
+        Number(_) => {}
+        Pos(expr) | Neg(expr) | Convert(expr, _) => find(expr),
+        Add(left, right)
+        | Sub(left, right)
+        | Mul(left, right)
+        | Div(left, right)
+        | Pow(left, right)
+        | Index(left, right) => {
+            find(left);
+            find(right);
+        }
+        Call(_, exprs) | List(exprs) => exprs.iter().for_each(find),
+        Matrix(rows) => rows.iter().flatten().for_each(find),
+    }
+}
+
+impl Solve {
+    // Returns None if the computation was interrupted.
+    fn solve(
+        &self,
+        program: &Program,
+        env: &mut Environment,
+    ) -> std::result::Result<Option<Value>, Box<dyn Error>> {
+        let usage = "usage: :solve <equation> between <a> and <b> [within <tolerance>], \
+            or :solve <equation> near <x> [within <tolerance>]";
+        let (args, tolerance) = match split_keyword(&self.args, "within") {
+            Some((args, tolerance)) => (args, Some(tolerance)),
+            None => (&self.args[..], None),
+        };
+        let (equation, bounds) = if let Some((equation, bounds)) = split_keyword(args, "near") {
+            (equation, vec![bounds])
+        } else if let Some((rest, b)) = split_keyword(args, "and") {
+            let (equation, a) = split_keyword(rest, "between").ok_or(usage)?;
+            (equation, vec![a, b])
+        } else {
+            return Err(usage.into());
+        };
+        // The equation is either an expression that should be 0, or two expressions that should be equal.
+        let equation = parse::parse(equation).map_err(|err| err.to_string())?;
+        let difference = match <[Statement; 1]>::try_from(equation.statements) {
+            Ok([Statement::Evaluate(expr)]) => expr,
+            Ok([Statement::Assign(mut exprs)]) if exprs.len() == 2 => {
+                let right = exprs.pop().unwrap();
+                let left = exprs.pop().unwrap();
+                p::Expression::Sub(Box::new(left), Box::new(right))
+            }
+            _ => return Err("expected one equation".into()),
+        };
+        let mut unknowns = vec![];
+        find_unknowns(&difference, program, &mut unknowns);
+        if unknowns.is_empty() {
+            find_unit_unknowns(&difference, program, &mut unknowns);
+        }
+        let [unknown] = &unknowns[..] else {
+            return Err(format!(
+                "the equation must have exactly one unknown, but it has {}",
+                unknowns.len()
+            )
+            .into());
+        };
+        let mut numbers = vec![];
+        for code in bounds.into_iter().chain(tolerance) {
+            let expr = compile::compile_standalone(&parse_expression(code)?, program)?;
+            let expr = LazyExpression::new(expr);
+            let Ok(value) = expr.evaluate(&mut env.evaluation_environment) else {
+                return Ok(None);
+            };
+            match value {
+                Value::Number(n) => numbers.push(n.clone()),
+                value => return Err(format!("expected a number: {value}").into()),
+            }
+        }
+        let tolerance = match tolerance {
+            Some(_) => numbers.pop().unwrap(),
+            None => solve::default_tolerance(),
+        };
+        if tolerance <= 0 {
+            return Err("the tolerance must be positive".into());
+        }
+        // The equation becomes a function of the unknown, in a copy of the program.
+        let mut program = program.clone();
+        let name = "solve equation"; // Not an identifier, so it doesn't hide anything.
+        let code = parse::Code {
+            statements: vec![Statement::Assign(vec![
+                p::Expression::Call(
+                    name.to_string(),
+                    vec![p::Expression::Identifier(unknown.clone())],
+                ),
+                difference,
+            ])],
+            sources: vec![],
+        };
+        compile::compile_into(code, &mut program)?;
+        let function = program.get_weak_function(name).unwrap();
+        let mut f = |x: &Rational| {
+            let call = Expression::Call(
+                function.clone(),
+                vec![Expression::Value(Value::Number(x.clone()))],
+            );
+            LazyExpression::new(call)
+                .evaluate(&mut env.evaluation_environment)
+                .cloned()
+        };
+        let mut numbers = numbers.into_iter();
+        let (a, b) = (numbers.next().unwrap(), numbers.next());
+        let result = match b {
+            Some(b) => solve::bisect(&mut f, a, b, &tolerance),
+            None => solve::solve_near(&mut f, a, &tolerance),
+        };
+        Ok(result.ok())
+    }
+}
+
+impl Command for Solve {
+    fn run(&self, program: &mut Program, env: &mut Environment, _session: &mut Session) -> Result {
+        env.ignore_ctrlc();
+        match self.solve(program, env) {
+            Ok(Some(value)) => env.output_value(&value)?,
+            Ok(None) => {}
+            Err(err) => env.output_error(ErrorKind::Command, err, None)?,
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+// Whether a name matches a pattern where * stands for any text. A pattern without * matches
+// the names that contain it.
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    fn matches_glob(pattern: &str, name: &str) -> bool {
+        match pattern.split_once('*') {
+            None => pattern == name,
+            Some((prefix, rest)) => {
+                let Some(name) = name.strip_prefix(prefix) else {
+                    return false;
+                };
+                name.char_indices()
+                    .map(|(i, _)| i)
+                    .chain([name.len()])
+                    .any(|i| matches_glob(rest, &name[i..]))
+            }
+        }
+    }
+    if pattern.contains('*') {
+        matches_glob(pattern, name)
+    } else {
+        name.contains(pattern)
+    }
+}
+
+fn parameters(n: usize) -> String {
+    match n {
+        1 => "1 parameter".to_string(),
+        n => format!("{n} parameters"),
+    }
+}
+
+struct List {
+    pattern: String,
+}
+
+impl Command for List {
+    fn run(&self, program: &mut Program, env: &mut Environment, _session: &mut Session) -> Result {
+        let mut names = program
+            .names()
+            .filter(|name| program.get_info(name).is_some())
+            .filter(|name| matches_pattern(&self.pattern, name))
+            .collect::<Vec<_>>();
+        names.sort();
+        if names.is_empty() {
+            writeln!(env.output(), "Nothing is defined.")?;
+            return Ok(ControlFlow::Continue(()));
+        }
+        let width = names.iter().map(|name| name.chars().count()).max().unwrap();
+        for name in names {
+            let description = match program.get_constant(name) {
+                Some(constant) if constant.value_if_found().is_some() => {
+                    "constant, evaluated".to_string()
+                }
+                Some(_) => "constant, not evaluated".to_string(),
+                None => format!(
+                    "function, {}",
+                    parameters(program.get_n_params(name).unwrap())
+                ),
+            };
+            writeln!(env.output(), "{name:width$}  {description}")?;
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+struct Show {
+    args: String,
+}
+
+impl Command for Show {
+    fn run(&self, program: &mut Program, env: &mut Environment, _session: &mut Session) -> Result {
+        let (markup, name) = match self.args.split_whitespace().collect::<Vec<_>>()[..] {
+            [name] => (None, name),
+            ["--latex", name] => (Some(Markup::Latex), name),
+            ["--mathml", name] => (Some(Markup::MathMl), name),
+            _ => {
+                let usage = "usage: :show [--latex | --mathml] <name>";
+                env.output_error(ErrorKind::Command, usage, None)?;
                 return Ok(ControlFlow::Continue(()));
             }
         };
-        super::run_str(&code, program, env)?;
+        let Some(info) = program.get_info(name) else {
+            let message = format!("no constant or function named \"{name}\"");
+            env.output_error(ErrorKind::Command, message, None)?;
+            return Ok(ControlFlow::Continue(()));
+        };
+        let left = program.left_side(name);
+        let Some(markup) = markup else {
+            match &info.source {
+                Some(source) => writeln!(env.output(), "{}", source.text)?,
+                None => writeln!(env.output(), "{left} = {}", info.value)?,
+            }
+            return Ok(ControlFlow::Continue(()));
+        };
+        let definition = markup.row(&[
+            left.typeset(markup),
+            markup.operator("=", "="),
+            info.value.typeset(markup),
+        ]);
+        writeln!(env.output(), "{}", markup.document(&definition))?;
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+struct Info {
+    name: String,
+}
+
+impl Command for Info {
+    fn run(&self, program: &mut Program, env: &mut Environment, _session: &mut Session) -> Result {
+        let name = &self.name[..];
+        let Some(info) = program.get_info(name) else {
+            let message = if let Some(n_params) = program.get_n_params(name) {
+                format!(
+                    "{name} is a built-in function with {}",
+                    parameters(n_params)
+                )
+            } else if program.get_builtin_constant(name).is_some() {
+                format!("{name} is a built-in constant")
+            } else if program.get_constant(name).is_some() {
+                format!("{name} is a result")
+            } else {
+                let message = format!("no constant or function named \"{name}\"");
+                env.output_error(ErrorKind::Command, message, None)?;
+                return Ok(ControlFlow::Continue(()));
+            };
+            writeln!(env.output(), "{message}.")?;
+            return Ok(ControlFlow::Continue(()));
+        };
+        writeln!(env.output(), "{} = {}", program.left_side(name), info.value)?;
+        if let Some(doc) = info.source.as_ref().and_then(|source| source.doc.as_ref()) {
+            writeln!(env.output(), "{doc}")?;
+        }
+        match &info.source {
+            Some(p::Source {
+                file: Some(file),
+                line,
+                ..
+            }) => writeln!(
+                env.output(),
+                "Defined in {} on line {line}.",
+                file.display()
+            )?,
+            Some(_) => writeln!(env.output(), "Defined in the REPL.")?,
+            None => writeln!(env.output(), "Generated by a command.")?,
+        }
+        let dependencies = program.dependencies(name);
+        if dependencies.is_empty() {
+            writeln!(env.output(), "It doesn't depend on anything.")?;
+        } else {
+            writeln!(env.output(), "Depends on: {}.", dependencies.join(", "))?;
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+struct Undo;
+
+impl Command for Undo {
+    fn run(&self, program: &mut Program, env: &mut Environment, session: &mut Session) -> Result {
+        if !session.history.undo(program) {
+            env.output_error(ErrorKind::Command, "nothing to undo", None)?;
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+struct Reset {
+    args: String,
+}
+
+impl Command for Reset {
+    fn run(&self, program: &mut Program, env: &mut Environment, session: &mut Session) -> Result {
+        match &self.args[..] {
+            "" => session.history.reset(program, false),
+            "--all" => session.history.reset(program, true),
+            _ => env.output_error(ErrorKind::Command, "usage: :reset [--all]", None)?,
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+struct Checkpoint {
+    name: String,
+}
+
+impl Command for Checkpoint {
+    fn run(&self, program: &mut Program, env: &mut Environment, session: &mut Session) -> Result {
+        if self.name.is_empty() {
+            let names = session.history.checkpoint_names();
+            if names.is_empty() {
+                writeln!(env.output(), "There are no checkpoints.")?;
+            } else {
+                writeln!(env.output(), "Checkpoints: {}.", names.join(", "))?;
+            }
+        } else {
+            session.history.checkpoint(&self.name, program);
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+struct Restore {
+    name: String,
+}
+
+impl Command for Restore {
+    fn run(&self, program: &mut Program, env: &mut Environment, session: &mut Session) -> Result {
+        if self.name.is_empty() {
+            env.output_error(ErrorKind::Command, "usage: :restore <name>", None)?;
+        } else if !session.history.restore(&self.name, program) {
+            let message = format!("no checkpoint named \"{}\"", self.name);
+            env.output_error(ErrorKind::Command, message, None)?;
+        }
         Ok(ControlFlow::Continue(()))
     }
 }
@@ -82,13 +663,41 @@ impl Command for Load {
 struct Help;
 
 impl Command for Help {
-    fn run(&self, _program: &mut Program, env: &mut Environment) -> Result {
-        write!(env.output(), include_str!("help.txt"))?;
+    fn run(&self, _program: &mut Program, env: &mut Environment, _session: &mut Session) -> Result {
+        write!(env.output(), "{}", include_str!("help.txt"))?;
         Ok(ControlFlow::Continue(()))
     }
 }
 
-pub fn run(command: &str, program: &mut Program, env: &mut Environment) -> Result {
+// The full names of the commands, for completion.
+pub const COMMANDS: [&str; 19] = [
+    "quit",
+    "format",
+    "delete",
+    "load",
+    "reload",
+    "watch",
+    "live",
+    "redefine",
+    "save",
+    "undo",
+    "reset",
+    "checkpoint",
+    "restore",
+    "diff",
+    "solve",
+    "list",
+    "show",
+    "info",
+    "help",
+];
+
+pub fn run(
+    command: &str,
+    program: &mut Program,
+    env: &mut Environment,
+    session: &mut Session,
+) -> Result {
     let command = command.trim().trim_start_matches(':').trim_start();
     let (name, args) = command
         .split_once(char::is_whitespace)
@@ -100,12 +709,27 @@ pub fn run(command: &str, program: &mut Program, env: &mut Environment) -> Resul
         "f" | "format" => Box::new(Format { new_format: args }),
         "d" | "delete" => Box::new(Delete { name: args }),
         "l" | "load" => Box::new(Load { file: args }),
+        "reload" => Box::new(Reload),
+        "watch" => Box::new(Watch { file: args }),
+        "live" => Box::new(Live { args }),
+        "redefine" => Box::new(Redefine { code: args }),
+        "save" => Box::new(Save { args }),
+        "undo" => Box::new(Undo),
+        "reset" => Box::new(Reset { args }),
+        "checkpoint" => Box::new(Checkpoint { name: args }),
+        "restore" => Box::new(Restore { name: args }),
+        "diff" => Box::new(Diff { args }),
+        "solve" => Box::new(Solve { args }),
+        "list" => Box::new(List { pattern: args }),
+        "show" => Box::new(Show { args }),
+        "info" => Box::new(Info { name: args }),
         "h" | "help" => Box::new(Help),
         _ => {
-            writeln!(env.error_output(), "unknown command: \":{}\"", name)?;
+            let message = format!("unknown command: \":{name}\"");
+            env.output_error(ErrorKind::Command, message, None)?;
             super::maybe_suggest_help(env)?;
             return Ok(ControlFlow::Continue(()));
         }
     };
-    cmd.run(program, env)
+    cmd.run(program, env, session)
 }
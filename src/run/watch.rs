@@ -0,0 +1,88 @@
+// Notices when loaded files change on disk.
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+
+pub struct Watcher {
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    files: Vec<(PathBuf, PathBuf)>, // The absolute path of each file, and the path it was loaded from.
+}
+
+impl Watcher {
+    pub fn new() -> notify::Result<Self> {
+        let (sender, events) = mpsc::channel();
+        Ok(Self {
+            watcher: notify::recommended_watcher(sender)?,
+            events,
+            files: vec![],
+        })
+    }
+
+    pub fn watch(&mut self, file: &Path) -> notify::Result<()> {
+        let path = file.canonicalize()?;
+        if self.files.iter().any(|(p, _)| *p == path) {
+            return Ok(());
+        }
+        // Editors often replace a file instead of writing to it, so the directory is watched.
+        let dir = path.parent().unwrap_or(&path);
+        self.watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        self.files.push((path, file.to_path_buf()));
+        Ok(())
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|(_, file)| file.as_path())
+    }
+
+    // The watched files that changed since the last call, as they were loaded, in the order they were watched.
+    pub fn changed_files(&mut self) -> Vec<PathBuf> {
+        let mut changed = vec![false; self.files.len()];
+        for event in self.events.try_iter().flatten() {
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                continue;
+            }
+            for path in &event.paths {
+                if let Some(i) = self.files.iter().position(|(p, _)| p == path) {
+                    changed[i] = true;
+                }
+            }
+        }
+        self.files
+            .iter()
+            .zip(changed)
+            .filter(|&(_, changed)| changed)
+            .map(|((_, file), _)| file.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn watch_test() {
+        let dir = std::env::temp_dir().join(format!("recalc_watch_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("lib.recalc");
+        fs::write(&file, "x = 1\n").unwrap();
+        let mut watcher = Watcher::new().unwrap();
+        watcher.watch(&file).unwrap();
+        assert!(watcher.changed_files().is_empty());
+        fs::write(dir.join("other.recalc"), "y = 1\n").unwrap();
+        fs::write(&file, "x = 2\n").unwrap();
+        let start = Instant::now();
+        let mut changed = vec![];
+        while changed.is_empty() && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+            changed = watcher.changed_files();
+        }
+        assert_eq!(changed, [file]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}